@@ -1,9 +1,9 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
 use kyt::tree::loss_fn::{Logit, ScoringFunction};
+use kyt::tree::split::binned::BinnedDataSet;
 use kyt::tree::{Tree, TreeConfig};
 use std::collections::HashMap;
 use std::time::Instant;
-use std::usize;
 
 fn create_sample_data(size: usize) -> (HashMap<String, Vec<f64>>, Vec<bool>) {
     let mut data = HashMap::new();
@@ -25,7 +25,10 @@ fn bench_tree_fit_size_10000(c: &mut Criterion) {
     let mut group = c.benchmark_group("Tree::");
     group.warm_up_time(std::time::Duration::from_secs(30));
 
-    let tree_config = TreeConfig { max_depth: 3 };
+    let tree_config = TreeConfig {
+        max_depth: 3,
+        ..Default::default()
+    };
     let score_fn = ScoringFunction::Logit(Logit::new(0.5));
     group.bench_function("size_10000", |b| {
         b.iter_custom(|iters| {
@@ -51,5 +54,79 @@ fn bench_tree_fit_size_10000(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_tree_fit_size_10000);
+fn bench_binned_tree_fit_size_100000(c: &mut Criterion) {
+    let mut group = c.benchmark_group("BinnedTree::");
+
+    let tree_config = TreeConfig {
+        max_depth: 3,
+        ..Default::default()
+    };
+    let score_fn = ScoringFunction::Logit(Logit::new(0.5));
+    let (data, target) = create_sample_data(100000);
+    let binned = BinnedDataSet::new(&data, 256).unwrap();
+    group.bench_function("size_100000", |b| {
+        b.iter(|| {
+            Tree::fit(
+                black_box(&binned),
+                black_box(&target),
+                black_box(&tree_config),
+                black_box(&score_fn),
+            )
+        });
+    });
+
+    group.finish();
+}
+
+fn create_predict_data(size: usize) -> (HashMap<String, Vec<f64>>, Vec<bool>) {
+    let mut data = HashMap::new();
+    for (idx, name) in ["F1", "F2", "F3", "F4"].iter().enumerate() {
+        let values: Vec<f64> = (0..size)
+            .map(|x| ((x * (2 * idx + 7919)) % 10007) as f64)
+            .collect();
+        data.insert(name.to_string(), values);
+    }
+    let target = (0..size)
+        .map(|x| (data["F1"][x] + data["F3"][x]) > 10007. || data["F2"][x] < 2000.)
+        .collect();
+    (data, target)
+}
+
+fn bench_predict_size_1000000(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Predict::");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(1_000_000));
+
+    let tree_config = TreeConfig {
+        max_depth: 8,
+        ..Default::default()
+    };
+    let score_fn = ScoringFunction::Logit(Logit::new(0.5));
+    let (data, target) = create_predict_data(1_000_000);
+    let binned = BinnedDataSet::new(&data, 256).unwrap();
+    let tree = Tree::fit(&binned, &target, &tree_config, &score_fn).unwrap();
+    let names = tree.feature_names();
+    let compiled = tree.compile(&names).unwrap();
+    let columns: Vec<&[f64]> = names.iter().map(|name| data[*name].as_slice()).collect();
+    let mut preds = vec![0.; 1_000_000];
+
+    group.bench_function("rows_size_1000000", |b| {
+        b.iter(|| tree.predict(black_box(&data)))
+    });
+    group.bench_function("parallel_size_1000000", |b| {
+        b.iter(|| tree.predict_par(black_box(&data)))
+    });
+    group.bench_function("compiled_parallel_size_1000000", |b| {
+        b.iter(|| compiled.predict_par_into(black_box(&columns), &mut preds))
+    });
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_tree_fit_size_10000,
+    bench_binned_tree_fit_size_100000,
+    bench_predict_size_1000000
+);
 criterion_main!(benches);
@@ -0,0 +1,696 @@
+use crate::metrics::{check_early_stopping_rounds, EvalHistory, EvalRecord, Metric};
+use crate::tree::loss_fn::gradients::{Objective, Regularization, SecondOrder};
+use crate::tree::loss_fn::multiclass::check_labels;
+use crate::tree::loss_fn::{Logit, Softmax};
+use crate::tree::split::{DataSet, Target, WeightedTarget};
+use crate::tree::{argmax, Tree, TreeConfig, TreeError};
+
+#[derive(Debug, Clone)]
+pub struct GradientBoostingConfig {
+    /// Number of boosting rounds, one tree is fitted per round.
+    pub n_estimators: usize,
+    /// Shrinkage applied to the leaves of every tree.
+    pub learning_rate: f64,
+    /// Raw prediction every row starts from, taken from the loss if `None`.
+    pub base_score: Option<f64>,
+    /// Regularization of the leaves of every tree, taken from the loss if `None`.
+    pub regularization: Option<Regularization>,
+    /// Number of rounds without improving the validation metric after which
+    /// `fit_with_eval` stops, every round is fitted if `None`.
+    pub early_stopping_rounds: Option<usize>,
+    /// Config of every tree.
+    pub tree_config: TreeConfig,
+}
+
+impl Default for GradientBoostingConfig {
+    fn default() -> Self {
+        GradientBoostingConfig {
+            n_estimators: 100,
+            learning_rate: 0.1,
+            base_score: None,
+            regularization: None,
+            early_stopping_rounds: None,
+            tree_config: TreeConfig {
+                max_depth: 3,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Additive model of trees: every round fits a tree on the gradients and hessians of the
+/// loss at the current raw predictions, its shrunk leaves are added to the predictions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradientBoosting<L> {
+    pub trees: Vec<Tree>,
+    pub base_score: f64,
+    pub learning_rate: f64,
+    loss: L,
+}
+
+/// Adds the shrunk leaves of `tree` to the raw predictions of `samples`.
+fn add_tree_leaves(
+    raw_preds: &mut [f64],
+    tree: &Tree,
+    samples: &impl DataSet,
+    learning_rate: f64,
+) -> Result<(), TreeError> {
+    for (raw_pred, leaf) in raw_preds.iter_mut().zip(tree.predict(samples)?) {
+        *raw_pred += learning_rate * leaf;
+    }
+    Ok(())
+}
+
+/// Replaces the leaves of `tree` by the values `loss` refits on the rows reaching them,
+/// the leaves it does not refit are kept.
+fn refit_leaves<T, L: Objective<T>>(
+    tree: &mut Tree,
+    samples: &impl DataSet,
+    target: &impl Target<T>,
+    raw_preds: &[f64],
+    loss: &L,
+) -> Result<(), TreeError> {
+    let mut leaf_rows = vec![Vec::new(); tree.n_leaves()];
+    for (row, leaf) in tree.predict_leaf_index(samples)?.into_iter().enumerate() {
+        leaf_rows[leaf].push(row);
+    }
+    for (leaf, rows) in tree.leaves_mut().into_iter().zip(leaf_rows) {
+        let rows = rows
+            .into_iter()
+            .map(|row| (target.value(row), raw_preds[row], target.weight(row)));
+        if let Some(value) = loss.refit_leaf(rows) {
+            leaf.prediction = Some(vec![value]);
+        }
+    }
+    Ok(())
+}
+
+fn check_learning_rate(config: &GradientBoostingConfig) {
+    if config.learning_rate <= 0. {
+        panic!("Learning rate must be gt than 0");
+    }
+}
+
+/// Weight of every row of `target`.
+fn row_weights<T>(target: &impl Target<T>) -> Vec<f64> {
+    (0..target.len()).map(|row| target.weight(row)).collect()
+}
+
+/// Metric of raw predictions once transformed by the loss.
+fn evaluate_raw<T>(
+    target: &impl Target<T>,
+    raw_preds: &[f64],
+    loss: &impl Objective<T>,
+    metric: &impl Metric<T>,
+) -> f64 {
+    let preds: Vec<f64> = raw_preds
+        .iter()
+        .map(|raw| loss.inverse_link(*raw))
+        .collect();
+    metric.evaluate(target, &preds)
+}
+
+impl<L: Clone> GradientBoosting<L> {
+    fn base_score<T>(config: &GradientBoostingConfig, target: &impl Target<T>, loss: &L) -> f64
+    where
+        L: Objective<T>,
+    {
+        check_learning_rate(config);
+        config
+            .base_score
+            .unwrap_or_else(|| loss.initial_prediction(target))
+    }
+    /// Fits the tree of a round on the gradients at `raw_preds`, which are then updated.
+    fn boost_round<T>(
+        samples: &impl DataSet,
+        target: &impl Target<T>,
+        raw_preds: &mut [f64],
+        config: &GradientBoostingConfig,
+        loss: &L,
+    ) -> Result<Tree, TreeError>
+    where
+        L: Objective<T>,
+    {
+        let gradients = WeightedTarget::new(loss.gradients(target, raw_preds), row_weights(target));
+        let regularization = config
+            .regularization
+            .unwrap_or_else(|| loss.regularization());
+        let score = SecondOrder::new(regularization);
+        let mut tree = Tree::fit(samples, &gradients, &config.tree_config, &score)?;
+        refit_leaves(&mut tree, samples, target, raw_preds, loss)?;
+        add_tree_leaves(raw_preds, &tree, samples, config.learning_rate)?;
+        Ok(tree)
+    }
+    pub fn fit<T>(
+        samples: &impl DataSet,
+        target: &impl Target<T>,
+        config: &GradientBoostingConfig,
+        loss: &L,
+    ) -> Result<GradientBoosting<L>, TreeError>
+    where
+        L: Objective<T>,
+    {
+        loss.validate_target(target)?;
+        let base_score = GradientBoosting::base_score(config, target, loss);
+        let mut raw_preds = vec![base_score; target.len()];
+        let trees = (0..config.n_estimators)
+            .map(|_| GradientBoosting::boost_round(samples, target, &mut raw_preds, config, loss))
+            .collect::<Result<Vec<_>, TreeError>>()?;
+        Ok(GradientBoosting {
+            trees,
+            base_score,
+            learning_rate: config.learning_rate,
+            loss: loss.clone(),
+        })
+    }
+    /// Fits the model round by round, evaluating `metric` on the transformed predictions
+    /// of the validation set after every round. Stops after `early_stopping_rounds` rounds
+    /// without improvement and keeps the trees of the best round, the metrics of every
+    /// round are returned along with the model (round 0 is the base score alone).
+    pub fn fit_with_eval<T>(
+        train: (&impl DataSet, &impl Target<T>),
+        valid: (&impl DataSet, &impl Target<T>),
+        config: &GradientBoostingConfig,
+        loss: &L,
+        metric: &impl Metric<T>,
+    ) -> Result<(GradientBoosting<L>, EvalHistory), TreeError>
+    where
+        L: Objective<T>,
+    {
+        check_early_stopping_rounds(config.early_stopping_rounds);
+        loss.validate_target(train.1)?;
+        loss.validate_target(valid.1)?;
+        let base_score = GradientBoosting::base_score(config, train.1, loss);
+        let mut train_raw_preds = vec![base_score; train.1.len()];
+        let mut valid_raw_preds = vec![base_score; valid.0.num_rows()?];
+        let mut history = EvalHistory::new(metric.higher_is_better());
+        history.record(EvalRecord {
+            stage: 0,
+            train: evaluate_raw(train.1, &train_raw_preds, loss, metric),
+            valid: evaluate_raw(valid.1, &valid_raw_preds, loss, metric),
+        });
+        let mut trees = Vec::with_capacity(config.n_estimators);
+        for round in 1..=config.n_estimators {
+            let tree = GradientBoosting::boost_round(
+                train.0,
+                train.1,
+                &mut train_raw_preds,
+                config,
+                loss,
+            )?;
+            add_tree_leaves(&mut valid_raw_preds, &tree, valid.0, config.learning_rate)?;
+            trees.push(tree);
+            let rounds_since_best = history.record(EvalRecord {
+                stage: round,
+                train: evaluate_raw(train.1, &train_raw_preds, loss, metric),
+                valid: evaluate_raw(valid.1, &valid_raw_preds, loss, metric),
+            });
+            if config
+                .early_stopping_rounds
+                .is_some_and(|rounds| rounds_since_best >= rounds)
+            {
+                break;
+            }
+        }
+        trees.truncate(history.best);
+        let model = GradientBoosting {
+            trees,
+            base_score,
+            learning_rate: config.learning_rate,
+            loss: loss.clone(),
+        };
+        Ok((model, history))
+    }
+    /// Base score plus the shrunk leaves of every tree, before the link function.
+    pub fn predict_raw(&self, samples: &impl DataSet) -> Result<Vec<f64>, TreeError> {
+        let mut raw_preds = vec![self.base_score; samples.num_rows()?];
+        for tree in self.trees.iter() {
+            add_tree_leaves(&mut raw_preds, tree, samples, self.learning_rate)?;
+        }
+        Ok(raw_preds)
+    }
+    /// Raw predictions mapped to the target space by the loss.
+    pub fn predict<T>(&self, samples: &impl DataSet) -> Result<Vec<f64>, TreeError>
+    where
+        L: Objective<T>,
+    {
+        Ok(self
+            .predict_raw(samples)?
+            .into_iter()
+            .map(|raw_pred| self.loss.inverse_link(raw_pred))
+            .collect())
+    }
+}
+
+impl GradientBoosting<Logit> {
+    /// Class probabilities `[1 - p, p]`, `p` being the probability of `true`.
+    pub fn predict_proba(&self, samples: &impl DataSet) -> Result<Vec<Vec<f64>>, TreeError> {
+        Ok(self
+            .predict(samples)?
+            .into_iter()
+            .map(|p| vec![1. - p, p])
+            .collect())
+    }
+}
+
+/// Softmax boosting over class labels in `0..n_classes`: every row has one raw score
+/// per class and every round fits one tree per class on the gradients of its score.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MulticlassBoosting {
+    /// Trees of every round, one per class.
+    pub trees: Vec<Vec<Tree>>,
+    /// Raw score of every class before any tree.
+    pub base_scores: Vec<f64>,
+    pub learning_rate: f64,
+    softmax: Softmax,
+}
+
+impl MulticlassBoosting {
+    /// Raw score of every class before any tree, `config.base_score` when set, the log
+    /// of the class frequencies otherwise.
+    fn base_scores(
+        config: &GradientBoostingConfig,
+        target: &impl Target<u32>,
+        softmax: &Softmax,
+    ) -> Vec<f64> {
+        check_learning_rate(config);
+        match config.base_score {
+            Some(base_score) => vec![base_score; softmax.n_classes()],
+            None => softmax.initial_predictions(target),
+        }
+    }
+    /// Fits the trees of a round on the gradients at `raw_preds`, which are then updated.
+    fn boost_round(
+        samples: &impl DataSet,
+        target: &impl Target<u32>,
+        raw_preds: &mut [Vec<f64>],
+        config: &GradientBoostingConfig,
+        softmax: &Softmax,
+    ) -> Result<Vec<Tree>, TreeError> {
+        let score = SecondOrder::new(
+            config
+                .regularization
+                .unwrap_or_else(|| softmax.regularization()),
+        );
+        let weights = row_weights(target);
+        // Every class is fitted on the gradients at the start of the round
+        let round = softmax
+            .gradients(target, raw_preds)
+            .into_iter()
+            .map(|gradients| {
+                let gradients = WeightedTarget::new(gradients, weights.clone());
+                Tree::fit(samples, &gradients, &config.tree_config, &score)
+            })
+            .collect::<Result<Vec<_>, TreeError>>()?;
+        add_round_leaves(raw_preds, &round, samples, config.learning_rate)?;
+        Ok(round)
+    }
+    /// Fits the model, `config.base_score` is used for every class when set, the log of
+    /// the class frequencies otherwise. Labels out of `0..n_classes` are an error.
+    pub fn fit(
+        samples: &impl DataSet,
+        target: &impl Target<u32>,
+        config: &GradientBoostingConfig,
+        softmax: &Softmax,
+    ) -> Result<MulticlassBoosting, TreeError> {
+        check_labels(target, softmax.n_classes())?;
+        let base_scores = MulticlassBoosting::base_scores(config, target, softmax);
+        let mut raw_preds = vec![base_scores.clone(); target.len()];
+        let trees = (0..config.n_estimators)
+            .map(|_| {
+                MulticlassBoosting::boost_round(samples, target, &mut raw_preds, config, softmax)
+            })
+            .collect::<Result<Vec<_>, TreeError>>()?;
+        Ok(MulticlassBoosting {
+            trees,
+            base_scores,
+            learning_rate: config.learning_rate,
+            softmax: *softmax,
+        })
+    }
+    /// Fits the model round by round, evaluating `metric` on the predicted classes of the
+    /// validation set after every round. Stops after `early_stopping_rounds` rounds
+    /// without improvement and keeps the rounds up to the best one, the metrics of every
+    /// round are returned along with the model (round 0 is the base scores alone).
+    pub fn fit_with_eval(
+        train: (&impl DataSet, &impl Target<u32>),
+        valid: (&impl DataSet, &impl Target<u32>),
+        config: &GradientBoostingConfig,
+        softmax: &Softmax,
+        metric: &impl Metric<u32>,
+    ) -> Result<(MulticlassBoosting, EvalHistory), TreeError> {
+        check_early_stopping_rounds(config.early_stopping_rounds);
+        check_labels(train.1, softmax.n_classes())?;
+        check_labels(valid.1, softmax.n_classes())?;
+        let base_scores = MulticlassBoosting::base_scores(config, train.1, softmax);
+        let mut train_raw_preds = vec![base_scores.clone(); train.1.len()];
+        let mut valid_raw_preds = vec![base_scores.clone(); valid.0.num_rows()?];
+        let mut history = EvalHistory::new(metric.higher_is_better());
+        history.record(EvalRecord {
+            stage: 0,
+            train: evaluate_classes(train.1, &train_raw_preds, metric),
+            valid: evaluate_classes(valid.1, &valid_raw_preds, metric),
+        });
+        let mut trees = Vec::with_capacity(config.n_estimators);
+        for round in 1..=config.n_estimators {
+            let round_trees = MulticlassBoosting::boost_round(
+                train.0,
+                train.1,
+                &mut train_raw_preds,
+                config,
+                softmax,
+            )?;
+            add_round_leaves(
+                &mut valid_raw_preds,
+                &round_trees,
+                valid.0,
+                config.learning_rate,
+            )?;
+            trees.push(round_trees);
+            let rounds_since_best = history.record(EvalRecord {
+                stage: round,
+                train: evaluate_classes(train.1, &train_raw_preds, metric),
+                valid: evaluate_classes(valid.1, &valid_raw_preds, metric),
+            });
+            if config
+                .early_stopping_rounds
+                .is_some_and(|rounds| rounds_since_best >= rounds)
+            {
+                break;
+            }
+        }
+        trees.truncate(history.best);
+        let model = MulticlassBoosting {
+            trees,
+            base_scores,
+            learning_rate: config.learning_rate,
+            softmax: *softmax,
+        };
+        Ok((model, history))
+    }
+    /// Raw score of every class for every sample.
+    pub fn predict_raw(&self, samples: &impl DataSet) -> Result<Vec<Vec<f64>>, TreeError> {
+        let mut raw_preds = vec![self.base_scores.clone(); samples.num_rows()?];
+        for round in self.trees.iter() {
+            add_round_leaves(&mut raw_preds, round, samples, self.learning_rate)?;
+        }
+        Ok(raw_preds)
+    }
+    /// Softmax of the raw scores of every sample.
+    pub fn predict_proba(&self, samples: &impl DataSet) -> Result<Vec<Vec<f64>>, TreeError> {
+        Ok(self
+            .predict_raw(samples)?
+            .iter()
+            .map(|raw_preds| self.softmax.probabilities(raw_preds))
+            .collect())
+    }
+    /// Most probable class of every sample.
+    pub fn predict(&self, samples: &impl DataSet) -> Result<Vec<f64>, TreeError> {
+        Ok(self
+            .predict_raw(samples)?
+            .iter()
+            .map(|raw_preds| argmax(raw_preds) as f64)
+            .collect())
+    }
+}
+
+/// Metric of the classes with the largest raw score.
+fn evaluate_classes(
+    target: &impl Target<u32>,
+    raw_preds: &[Vec<f64>],
+    metric: &impl Metric<u32>,
+) -> f64 {
+    let preds: Vec<f64> = raw_preds
+        .iter()
+        .map(|raw_preds| argmax(raw_preds) as f64)
+        .collect();
+    metric.evaluate(target, &preds)
+}
+
+/// Adds the shrunk leaves of the tree of every class to the raw scores of `samples`.
+fn add_round_leaves(
+    raw_preds: &mut [Vec<f64>],
+    round: &[Tree],
+    samples: &impl DataSet,
+    learning_rate: f64,
+) -> Result<(), TreeError> {
+    for (class, tree) in round.iter().enumerate() {
+        for (raw_pred, leaf) in raw_preds.iter_mut().zip(tree.predict(samples)?) {
+            raw_pred[class] += learning_rate * leaf;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{Accuracy, LogLoss, Metric};
+    use crate::test_utils::{noisy_step, Lcg};
+    use crate::tree::loss_fn::ScoreError;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_first_round_matches_logit_tree() {
+        let data = HashMap::from([("F1".to_string(), vec![1., 2., 3., 4.])]);
+        let target = vec![true, false, false, true];
+        let logit = Logit::new(0.25);
+        let config = GradientBoostingConfig {
+            n_estimators: 1,
+            learning_rate: 0.5,
+            tree_config: TreeConfig {
+                max_depth: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let model = GradientBoosting::fit(&data, &target, &config, &logit).unwrap();
+        assert_eq!((1f64 / 3.).ln(), model.base_score, "Wrong base score");
+        let logit_tree = Tree::fit(&data, &target, &config.tree_config, &logit).unwrap();
+        assert_eq!(logit_tree, model.trees[0], "Wrong first tree");
+
+        let leaves = logit_tree.predict(&data).unwrap();
+        let raw_preds = model.predict_raw(&data).unwrap();
+        for (raw_pred, leaf) in raw_preds.iter().zip(leaves) {
+            assert!((raw_pred - model.base_score - 0.5 * leaf).abs() < 1e-12);
+        }
+    }
+    #[test]
+    fn test_regularized_leaves() {
+        let data = HashMap::from([("F1".to_string(), vec![1., 2., 3., 4., 5., 6.])]);
+        let target = vec![true, true, true, false, false, true];
+        let config = GradientBoostingConfig {
+            n_estimators: 1,
+            learning_rate: 1.,
+            tree_config: TreeConfig {
+                max_depth: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let regularization = Regularization::new(2., 0., 0., 0.);
+        let logit = Logit::new(0.5).with_regularization(regularization);
+        let plain = GradientBoosting::fit(&data, &target, &config, &Logit::new(0.5)).unwrap();
+        let shrunk = GradientBoosting::fit(&data, &target, &config, &logit).unwrap();
+        let logit_tree = Tree::fit(&data, &target, &config.tree_config, &logit).unwrap();
+        assert_eq!(
+            logit_tree, shrunk.trees[0],
+            "Loss regularization not applied"
+        );
+        let split = |tree: &Tree| tree.split_info.as_ref().unwrap().value;
+        assert_eq!(split(&plain.trees[0]), split(&shrunk.trees[0]));
+        let plain_leaves = plain.trees[0].predict(&data).unwrap();
+        let shrunk_leaves = shrunk.trees[0].predict(&data).unwrap();
+        for (plain, shrunk) in plain_leaves.iter().zip(shrunk_leaves.iter()) {
+            assert!(
+                shrunk.abs() < plain.abs(),
+                "Lambda should shrink the leaves"
+            );
+        }
+
+        let config = GradientBoostingConfig {
+            regularization: Some(regularization),
+            ..config
+        };
+        let model = GradientBoosting::fit(&data, &target, &config, &Logit::new(0.5)).unwrap();
+        assert_eq!(
+            shrunk.trees, model.trees,
+            "Config regularization not applied"
+        );
+    }
+    #[test]
+    fn test_boosting_rounds() {
+        let data = HashMap::from([("F1".to_string(), (0..20).map(f64::from).collect::<Vec<_>>())]);
+        let target: Vec<bool> = (0..20).map(|v| v % 7 < 3).collect();
+        let losses: Vec<f64> = [1, 10, 50]
+            .into_iter()
+            .map(|n_estimators| {
+                let config = GradientBoostingConfig {
+                    n_estimators,
+                    ..Default::default()
+                };
+                let model =
+                    GradientBoosting::fit(&data, &target, &config, &Logit::new(0.5)).unwrap();
+                let probabilities = model.predict_proba(&data).unwrap();
+                assert!(probabilities
+                    .iter()
+                    .all(|proba| (proba[0] + proba[1] - 1.).abs() < 1e-12));
+                LogLoss.evaluate(&target, &model.predict(&data).unwrap())
+            })
+            .collect();
+        assert!(
+            losses.windows(2).all(|pair| pair[1] < pair[0]),
+            "Training loss should decrease with rounds: {:?}",
+            losses
+        );
+    }
+    #[test]
+    fn test_early_stopping() {
+        let mut rng = Lcg::new(5);
+        let (train_data, train_target) = noisy_step(&mut rng, 200, 0.3);
+        let (valid_data, valid_target) = noisy_step(&mut rng, 200, 0.3);
+        let config = GradientBoostingConfig {
+            n_estimators: 200,
+            learning_rate: 0.5,
+            early_stopping_rounds: Some(5),
+            tree_config: TreeConfig {
+                max_depth: 6,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (model, history) = GradientBoosting::fit_with_eval(
+            (&train_data, &train_target),
+            (&valid_data, &valid_target),
+            &config,
+            &Logit::new(0.5),
+            &LogLoss,
+        )
+        .unwrap();
+        assert!(
+            history.records.len() < 200,
+            "Early stopping did not trigger"
+        );
+        assert_eq!(history.best + 6, history.records.len(), "Wrong stop round");
+        let best = history.best_record().unwrap();
+        assert_eq!(best.stage, model.trees.len(), "Model not truncated");
+        let valid_loss = LogLoss.evaluate(&valid_target, &model.predict(&valid_data).unwrap());
+        assert!((valid_loss - best.valid).abs() < 1e-12, "Wrong best score");
+        let last = history.records.last().unwrap();
+        assert!(last.train < best.train, "Train loss should keep decreasing");
+    }
+    #[test]
+    fn test_multiclass_boosting() {
+        let values: Vec<f64> = (0..30).map(f64::from).collect();
+        let target: Vec<u32> = (0..30).map(|v| (v / 10 + 1) % 3).collect();
+        let data = HashMap::from([("F1".to_string(), values)]);
+        let softmax = Softmax::new(3);
+        let config = GradientBoostingConfig {
+            n_estimators: 0,
+            ..Default::default()
+        };
+        let prior = MulticlassBoosting::fit(&data, &target, &config, &softmax).unwrap();
+        for proba in prior.predict_proba(&data).unwrap() {
+            assert!(
+                proba.iter().all(|p| (p - 1. / 3.).abs() < 1e-12),
+                "Wrong prior"
+            );
+        }
+
+        let config = GradientBoostingConfig {
+            n_estimators: 20,
+            learning_rate: 0.5,
+            tree_config: TreeConfig {
+                max_depth: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let model = MulticlassBoosting::fit(&data, &target, &config, &softmax).unwrap();
+        assert_eq!(20, model.trees.len(), "Wrong number of rounds");
+        assert!(model.trees.iter().all(|round| round.len() == 3));
+        let probabilities = model.predict_proba(&data).unwrap();
+        for (proba, label) in probabilities.iter().zip(target.iter()) {
+            assert!((proba.iter().sum::<f64>() - 1.).abs() < 1e-12);
+            assert!(
+                proba[label as usize] > 0.9,
+                "Wrong probabilities {:?}",
+                proba
+            );
+        }
+        let preds = model.predict(&data).unwrap();
+        assert_eq!(1., Accuracy.evaluate(&target, &preds), "Wrong classes");
+
+        let result = MulticlassBoosting::fit(&data, &target, &config, &Softmax::new(2));
+        assert!(matches!(
+            result,
+            Err(TreeError::ScoreError(ScoreError::LabelOutOfRange {
+                label: 2,
+                ..
+            }))
+        ));
+    }
+    /// Classes `F1 * 3` of uniform `F1` values, a third of the labels are random.
+    fn noisy_classes(rng: &mut Lcg, n_rows: usize) -> (HashMap<String, Vec<f64>>, Vec<u32>) {
+        let values: Vec<f64> = (0..n_rows).map(|_| rng.uniform()).collect();
+        let target = values
+            .iter()
+            .map(|v| match rng.uniform() < 1. / 3. {
+                true => (rng.uniform() * 3.) as u32,
+                false => (v * 3.) as u32,
+            })
+            .collect();
+        (HashMap::from([("F1".to_string(), values)]), target)
+    }
+    #[test]
+    fn test_multiclass_early_stopping() {
+        let mut rng = Lcg::new(3);
+        let (train_data, train_target) = noisy_classes(&mut rng, 300);
+        let (valid_data, valid_target) = noisy_classes(&mut rng, 300);
+        let config = GradientBoostingConfig {
+            n_estimators: 100,
+            learning_rate: 0.5,
+            early_stopping_rounds: Some(5),
+            tree_config: TreeConfig {
+                max_depth: 6,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (model, history) = MulticlassBoosting::fit_with_eval(
+            (&train_data, &train_target),
+            (&valid_data, &valid_target),
+            &config,
+            &Softmax::new(3),
+            &Accuracy,
+        )
+        .unwrap();
+        assert!(
+            history.records.len() < 101,
+            "Early stopping did not trigger"
+        );
+        assert_eq!(history.best + 6, history.records.len(), "Wrong stop round");
+        let best = history.best_record().unwrap();
+        assert!(best.stage > 0, "Boosting should beat the prior");
+        assert_eq!(best.stage, model.trees.len(), "Model not truncated");
+        let valid_accuracy = Accuracy.evaluate(&valid_target, &model.predict(&valid_data).unwrap());
+        assert_eq!(best.valid, valid_accuracy, "Wrong best score");
+    }
+    #[test]
+    #[should_panic(expected = "Early stopping rounds must be gt than 0")]
+    fn test_zero_early_stopping_rounds() {
+        let data = HashMap::from([("F1".to_string(), vec![1., 2.])]);
+        let target = vec![true, false];
+        let config = GradientBoostingConfig {
+            early_stopping_rounds: Some(0),
+            ..Default::default()
+        };
+        let _ = GradientBoosting::fit_with_eval(
+            (&data, &target),
+            (&data, &target),
+            &config,
+            &Logit::new(0.5),
+            &LogLoss,
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use super::random_forest::{RandomForest, RandomForestConfig};
+use crate::tree::loss_fn::Score;
+use crate::tree::split::{DataSet, SplitStrategy, Target};
+use crate::tree::{MaxFeatures, Tree, TreeConfig, TreeError};
+
+/// Extremely randomized trees: a forest whose trees split numerical features on a single
+/// random threshold per feature. Trees are fitted on every row unless `bootstrap` is set.
+#[derive(Debug, PartialEq)]
+pub struct ExtraTrees {
+    forest: RandomForest,
+}
+
+impl ExtraTrees {
+    /// Fully grown trees on every row, splits are searched on `sqrt(n_features)` features.
+    pub fn default_config() -> RandomForestConfig {
+        RandomForestConfig {
+            bootstrap: false,
+            tree_config: TreeConfig {
+                max_depth: usize::MAX,
+                max_features: MaxFeatures::Sqrt,
+                split_strategy: SplitStrategy::Random,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+    /// Fits the forest, `config.tree_config.split_strategy` is always `Random`.
+    pub fn fit<T, S: Score<T>>(
+        samples: &impl DataSet,
+        target: &impl Target<T>,
+        config: &RandomForestConfig,
+        score_fn: &S,
+    ) -> Result<ExtraTrees, TreeError> {
+        let config = RandomForestConfig {
+            tree_config: TreeConfig {
+                split_strategy: SplitStrategy::Random,
+                ..config.tree_config.clone()
+            },
+            ..config.clone()
+        };
+        let forest = RandomForest::fit(samples, target, &config, score_fn)?;
+        Ok(ExtraTrees { forest })
+    }
+    pub fn trees(&self) -> &[Tree] {
+        &self.forest.trees
+    }
+    pub fn forest(&self) -> &RandomForest {
+        &self.forest
+    }
+    pub fn predict_vector(&self, samples: &impl DataSet) -> Result<Vec<Vec<f64>>, TreeError> {
+        self.forest.predict_vector(samples)
+    }
+    pub fn predict(&self, samples: &impl DataSet) -> Result<Vec<f64>, TreeError> {
+        self.forest.predict(samples)
+    }
+    pub fn predict_proba(&self, samples: &impl DataSet) -> Result<Vec<Vec<f64>>, TreeError> {
+        self.forest.predict_proba(samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{MeanSquaredError, Metric};
+    use crate::tree::loss_fn::SquaredError;
+    use crate::tree::split::binned::BinnedDataSet;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_random_thresholds() {
+        let data = HashMap::from([("F1".to_string(), (0..100).map(f64::from).collect())]);
+        let target: Vec<f64> = (0..100).map(|v| (v / 10) as f64).collect();
+        let tree_config = TreeConfig {
+            max_depth: 1,
+            split_strategy: SplitStrategy::Random,
+            ..Default::default()
+        };
+        let thresholds: Vec<f64> = (0..5)
+            .map(|seed| {
+                let tree_config = TreeConfig {
+                    seed,
+                    ..tree_config.clone()
+                };
+                let tree = Tree::fit(&data, &target, &tree_config, &SquaredError).unwrap();
+                let threshold = tree.split_info.unwrap().value;
+                assert!((1. ..=99.).contains(&threshold), "Threshold out of range");
+                threshold
+            })
+            .collect();
+        assert!(
+            thresholds.windows(2).any(|pair| pair[0] != pair[1]),
+            "Thresholds should depend on the seed"
+        );
+
+        // Random thresholds are moved to bin boundaries
+        let binned = BinnedDataSet::new(&data, 8).unwrap();
+        let tree = Tree::fit(&binned, &target, &tree_config, &SquaredError).unwrap();
+        let threshold = tree.split_info.as_ref().unwrap().value;
+        let feature = binned.feature("F1").unwrap();
+        assert!(
+            feature.thresholds().contains(&threshold),
+            "Not a bin boundary"
+        );
+        assert_eq!(tree.predict(&data).unwrap(), tree.predict(&binned).unwrap());
+    }
+    #[test]
+    fn test_extra_trees() {
+        let data = HashMap::from([
+            ("F1".to_string(), (0..50).map(f64::from).collect()),
+            (
+                "F2".to_string(),
+                (0..50).map(|v| f64::from(v % 7)).collect(),
+            ),
+        ]);
+        let target: Vec<f64> = (0..50).map(|v| f64::from(v).sqrt()).collect();
+        let default_config = ExtraTrees::default_config();
+        let config = RandomForestConfig {
+            n_estimators: 20,
+            seed: 1,
+            tree_config: TreeConfig {
+                max_features: MaxFeatures::All,
+                ..default_config.tree_config.clone()
+            },
+            ..default_config
+        };
+        let model = ExtraTrees::fit(&data, &target, &config, &SquaredError).unwrap();
+        let same_seed = ExtraTrees::fit(&data, &target, &config, &SquaredError).unwrap();
+        assert_eq!(model, same_seed, "Same seed should fit the same trees");
+        assert_ne!(model.trees()[0], model.trees()[1], "Trees should differ");
+
+        let preds = model.predict(&data).unwrap();
+        let mse = MeanSquaredError.evaluate(&target, &preds);
+        assert!(mse < 1e-2, "Fully grown trees should fit the data: {}", mse);
+        assert!(
+            matches!(
+                model.predict_proba(&data),
+                Err(TreeError::NoProbabilityLeaves)
+            ),
+            "Regression leaves are not probabilities"
+        );
+    }
+}
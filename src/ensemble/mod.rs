@@ -0,0 +1,7 @@
+pub mod boosting;
+pub mod extra_trees;
+pub mod random_forest;
+
+pub use boosting::{GradientBoosting, GradientBoostingConfig, MulticlassBoosting};
+pub use extra_trees::ExtraTrees;
+pub use random_forest::{RandomForest, RandomForestConfig};
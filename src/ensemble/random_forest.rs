@@ -0,0 +1,313 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::metrics::Metric;
+use crate::tree::loss_fn::Score;
+use crate::tree::split::{DataSet, Target, TargetRows};
+use crate::tree::{leaf_prediction, MaxFeatures, Tree, TreeConfig, TreeError};
+
+#[derive(Debug, Clone)]
+pub struct RandomForestConfig {
+    /// Number of trees of the forest.
+    pub n_estimators: usize,
+    /// Fit every tree on a bootstrap sample of the rows instead of on all of them.
+    pub bootstrap: bool,
+    /// Keep the out-of-bag predictions of every row, requires `bootstrap`.
+    pub oob_score: bool,
+    /// Seed of the bootstrap samples and of the feature sampling of every tree.
+    pub seed: u64,
+    /// Config shared by the trees, `tree_config.seed` is overridden for every tree.
+    pub tree_config: TreeConfig,
+}
+
+impl Default for RandomForestConfig {
+    /// Fully grown trees on bootstrap samples, splits are searched on `sqrt(n_features)`
+    /// features.
+    fn default() -> Self {
+        RandomForestConfig {
+            n_estimators: 100,
+            bootstrap: true,
+            oob_score: false,
+            seed: 0,
+            tree_config: TreeConfig {
+                max_depth: usize::MAX,
+                max_features: MaxFeatures::Sqrt,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Bagging of decision trees: every tree is fitted on a bootstrap sample of the rows,
+/// predictions average the leaves of the trees.
+#[derive(Debug, PartialEq)]
+pub struct RandomForest {
+    pub trees: Vec<Tree>,
+    /// Leaves of every row averaged over the trees it was out-of-bag for, `None` if
+    /// the row was in the sample of every tree.
+    oob_predictions: Option<Vec<Option<Vec<f64>>>>,
+    /// Whether the leaves hold class probabilities, see `Score::probability_leaves`.
+    probability_leaves: bool,
+}
+
+/// Adds the leaves of a tree to the sums of the rows that were out of its bag.
+fn accumulate_leaves(
+    sums: &mut [Option<(Vec<f64>, usize)>],
+    leaves: Vec<Vec<f64>>,
+    in_bag: &[bool],
+) {
+    for ((sum, leaf), in_bag) in sums.iter_mut().zip(leaves).zip(in_bag) {
+        if *in_bag {
+            continue;
+        }
+        match sum {
+            Some((values, count)) => {
+                values.iter_mut().zip(leaf).for_each(|(v, l)| *v += l);
+                *count += 1;
+            }
+            None => *sum = Some((leaf, 1)),
+        }
+    }
+}
+
+impl RandomForest {
+    pub fn fit<T, S: Score<T>>(
+        samples: &impl DataSet,
+        target: &impl Target<T>,
+        config: &RandomForestConfig,
+        score_fn: &S,
+    ) -> Result<RandomForest, TreeError> {
+        if config.n_estimators == 0 {
+            panic!("A forest needs at least one tree");
+        }
+        if config.oob_score && !config.bootstrap {
+            panic!("Out-of-bag predictions require bootstrap samples");
+        }
+        let n_rows = target.len();
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let tree_seeds: Vec<u64> = (0..config.n_estimators).map(|_| rng.gen()).collect();
+        let fitted = tree_seeds
+            .par_iter()
+            .map(|seed| {
+                let mut rng = StdRng::seed_from_u64(*seed);
+                let rows: Vec<usize> = match config.bootstrap {
+                    true => (0..n_rows).map(|_| rng.gen_range(0..n_rows)).collect(),
+                    false => (0..n_rows).collect(),
+                };
+                // Only out-of-bag predictions need the rows of the sample
+                let in_bag = config.oob_score.then(|| {
+                    let mut in_bag = vec![false; n_rows];
+                    rows.iter().for_each(|row| in_bag[*row] = true);
+                    in_bag
+                });
+                let tree_config = TreeConfig {
+                    seed: rng.gen(),
+                    ..config.tree_config.clone()
+                };
+                let tree = Tree::fit_rows(samples, target, rows, &tree_config, score_fn)?;
+                Ok((tree, in_bag))
+            })
+            .collect::<Result<Vec<_>, TreeError>>()?;
+
+        let oob_predictions = match config.oob_score {
+            true => {
+                let mut sums = vec![None; n_rows];
+                for (tree, in_bag) in fitted.iter() {
+                    let in_bag = in_bag.as_deref().expect("Samples are kept for oob_score");
+                    accumulate_leaves(&mut sums, tree.predict_vector(samples)?, in_bag);
+                }
+                let averages = sums
+                    .into_iter()
+                    .map(|sum| {
+                        sum.map(|(values, count)| {
+                            values.into_iter().map(|v| v / count as f64).collect()
+                        })
+                    })
+                    .collect();
+                Some(averages)
+            }
+            false => None,
+        };
+        Ok(RandomForest {
+            trees: fitted.into_iter().map(|(tree, _)| tree).collect(),
+            oob_predictions,
+            probability_leaves: score_fn.probability_leaves(),
+        })
+    }
+    /// Leaves of every sample averaged over the trees.
+    pub fn predict_vector(&self, samples: &impl DataSet) -> Result<Vec<Vec<f64>>, TreeError> {
+        let sums = self
+            .trees
+            .par_iter()
+            .map(|tree| tree.predict_vector(samples))
+            .try_reduce_with(|mut acc, leaves| {
+                for (sum, leaf) in acc.iter_mut().zip(leaves) {
+                    sum.iter_mut().zip(leaf).for_each(|(s, l)| *s += l);
+                }
+                Ok(acc)
+            })
+            .expect("A forest has at least one tree")?;
+        let n_trees = self.trees.len() as f64;
+        Ok(sums
+            .into_iter()
+            .map(|sum| sum.into_iter().map(|s| s / n_trees).collect())
+            .collect())
+    }
+    /// Average of the tree predictions, which follows `Tree::predict`: binary classification
+    /// forests predict the averaged probability of `true`, multiclass ones the most probable
+    /// class of the averaged probabilities and regression forests the averaged value.
+    pub fn predict(&self, samples: &impl DataSet) -> Result<Vec<f64>, TreeError> {
+        Ok(self
+            .predict_vector(samples)?
+            .iter()
+            .map(|leaf| leaf_prediction(leaf))
+            .collect())
+    }
+    /// Averaged probability of every class of every sample, `[1 - p, p]` for binary
+    /// classification. Fails unless the leaves hold class probabilities.
+    pub fn predict_proba(&self, samples: &impl DataSet) -> Result<Vec<Vec<f64>>, TreeError> {
+        if !self.probability_leaves {
+            return Err(TreeError::NoProbabilityLeaves);
+        }
+        Ok(self
+            .predict_vector(samples)?
+            .into_iter()
+            .map(|leaf| match leaf.as_slice() {
+                [p] => vec![1. - p, *p],
+                _ => leaf,
+            })
+            .collect())
+    }
+    /// Out-of-bag prediction of every row, `None` if the row was in every bootstrap
+    /// sample or if out-of-bag predictions were not requested.
+    pub fn oob_predictions(&self) -> Option<Vec<Option<f64>>> {
+        self.oob_predictions.as_ref().map(|preds| {
+            preds
+                .iter()
+                .map(|pred| pred.as_deref().map(leaf_prediction))
+                .collect()
+        })
+    }
+    /// Metric of the out-of-bag predictions, on the rows that have one.
+    pub fn oob_score<T>(&self, target: &impl Target<T>, metric: &impl Metric<T>) -> Option<f64> {
+        let (rows, preds): (Vec<usize>, Vec<f64>) = self
+            .oob_predictions()?
+            .into_iter()
+            .enumerate()
+            .filter_map(|(row, pred)| pred.map(|pred| (row, pred)))
+            .unzip();
+        Some(metric.evaluate(&TargetRows::new(target, &rows), &preds))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Accuracy;
+    use crate::test_utils::Lcg;
+    use crate::tree::loss_fn::{Gini, MulticlassGini, ScoringFunction};
+    use std::collections::HashMap;
+
+    fn noisy_data(n_rows: usize) -> (HashMap<String, Vec<f64>>, Vec<bool>) {
+        // Deterministic pseudo random data, F3 is pure noise
+        let mut rng = Lcg::new(7);
+        let mut data: HashMap<String, Vec<f64>> = HashMap::new();
+        for name in ["F1", "F2", "F3"] {
+            data.insert(
+                name.to_string(),
+                (0..n_rows).map(|_| rng.uniform()).collect(),
+            );
+        }
+        let target = (0..n_rows)
+            .map(|row| (data["F1"][row] + data["F2"][row] > 1.) ^ (rng.uniform() < 0.1))
+            .collect();
+        (data, target)
+    }
+
+    #[test]
+    fn test_max_features() {
+        assert_eq!(10, MaxFeatures::All.n_features(10));
+        assert_eq!(3, MaxFeatures::Sqrt.n_features(10));
+        assert_eq!(3, MaxFeatures::Log2.n_features(10));
+        assert_eq!(5, MaxFeatures::Fraction(0.5).n_features(10));
+        assert_eq!(1, MaxFeatures::Fraction(0.01).n_features(10));
+        assert_eq!(10, MaxFeatures::Count(20).n_features(10));
+        assert_eq!(10, MaxFeatures::Fraction(1.).n_features(10));
+    }
+    #[test]
+    #[should_panic(expected = "Max features fraction must be gt than 0 and lte than 1")]
+    fn test_nan_max_features_fraction() {
+        MaxFeatures::Fraction(f64::NAN).n_features(10);
+    }
+    #[test]
+    fn test_seeded_forest() {
+        let (data, target) = noisy_data(200);
+        let config = RandomForestConfig {
+            n_estimators: 10,
+            seed: 3,
+            ..Default::default()
+        };
+        let score_fn = ScoringFunction::Gini(Gini);
+        let forest = RandomForest::fit(&data, &target, &config, &score_fn).unwrap();
+        let same_seed = RandomForest::fit(&data, &target, &config, &score_fn).unwrap();
+        assert_eq!(forest, same_seed, "Same seed should fit the same forest");
+
+        let other_config = RandomForestConfig { seed: 4, ..config };
+        let other_seed = RandomForest::fit(&data, &target, &other_config, &score_fn).unwrap();
+        assert_ne!(forest, other_seed, "Seeds should change the forest");
+        assert_ne!(forest.trees[0], forest.trees[1], "Trees should differ");
+    }
+    #[test]
+    fn test_forest_predictions() {
+        let (data, target) = noisy_data(300);
+        let config = RandomForestConfig {
+            n_estimators: 30,
+            oob_score: true,
+            tree_config: TreeConfig {
+                max_depth: 6,
+                max_features: MaxFeatures::Count(2),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let forest = RandomForest::fit(&data, &target, &config, &ScoringFunction::Gini(Gini));
+        let forest = forest.unwrap();
+
+        let oob_predictions = forest.oob_predictions().unwrap();
+        assert!(oob_predictions.iter().all(Option::is_some), "Missing oob");
+        let oob_accuracy = forest.oob_score(&target, &Accuracy).unwrap();
+        assert!(
+            oob_accuracy > 0.75,
+            "Oob accuracy too low: {}",
+            oob_accuracy
+        );
+        let accuracy = Accuracy.evaluate(&target, &forest.predict(&data).unwrap());
+        assert!(
+            accuracy > oob_accuracy,
+            "Training accuracy should be optimistic"
+        );
+
+        let probabilities = forest.predict_proba(&data).unwrap();
+        let preds = forest.predict(&data).unwrap();
+        for (proba, pred) in probabilities.iter().zip(preds) {
+            assert_eq!(vec![1. - pred, pred], *proba, "Wrong probabilities");
+        }
+    }
+    #[test]
+    fn test_multiclass_forest() {
+        let data = HashMap::from([("F1".to_string(), (0..30).map(f64::from).collect())]);
+        let target: Vec<u32> = (0..30).map(|v| v / 10).collect();
+        let config = RandomForestConfig {
+            n_estimators: 5,
+            ..Default::default()
+        };
+        let forest = RandomForest::fit(&data, &target, &config, &MulticlassGini::new(3)).unwrap();
+        let probabilities = forest.predict_proba(&data).unwrap();
+        assert!(probabilities
+            .iter()
+            .all(|proba| (proba.iter().sum::<f64>() - 1.).abs() < 1e-12));
+        let preds = forest.predict(&data).unwrap();
+        assert!(Accuracy.evaluate(&target, &preds) > 0.9, "Wrong classes");
+    }
+}
@@ -1 +1,6 @@
+pub mod ensemble;
+pub mod metrics;
 pub mod tree;
+
+#[cfg(test)]
+mod test_utils;
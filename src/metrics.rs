@@ -0,0 +1,259 @@
+use crate::tree::split::Target;
+
+/// Evaluation of predictions against a target, e.g. for out-of-bag or validation scores.
+pub trait Metric<T>: Sync {
+    fn evaluate(&self, target: &impl Target<T>, preds: &[f64]) -> f64;
+    /// Whether greater values are better, metrics are losses unless they say otherwise.
+    fn higher_is_better(&self) -> bool {
+        false
+    }
+}
+
+/// Train and validation metrics of a stage of a fit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EvalRecord {
+    /// Depth of a tree or number of boosting rounds.
+    pub stage: usize,
+    pub train: f64,
+    pub valid: f64,
+}
+
+/// Metrics of every stage evaluated while fitting, e.g. to plot learning curves.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalHistory {
+    pub records: Vec<EvalRecord>,
+    /// Index in `records` of the stage with the best validation metric, the first on ties.
+    pub best: usize,
+    higher_is_better: bool,
+}
+
+impl EvalHistory {
+    pub fn new(higher_is_better: bool) -> Self {
+        EvalHistory {
+            records: Vec::new(),
+            best: 0,
+            higher_is_better,
+        }
+    }
+    /// Records a stage, returns the number of stages recorded since the best one.
+    pub fn record(&mut self, record: EvalRecord) -> usize {
+        self.records.push(record);
+        let last = self.records.len() - 1;
+        let best = self.records[self.best].valid;
+        let improved = match self.higher_is_better {
+            true => record.valid > best,
+            false => record.valid < best,
+        };
+        if improved {
+            self.best = last;
+        }
+        last - self.best
+    }
+    pub fn best_record(&self) -> Option<&EvalRecord> {
+        self.records.get(self.best)
+    }
+}
+
+/// Panics if `early_stopping_rounds` would stop before any stage is fitted.
+pub(crate) fn check_early_stopping_rounds(early_stopping_rounds: Option<usize>) {
+    if early_stopping_rounds == Some(0) {
+        panic!("Early stopping rounds must be gt than 0");
+    }
+}
+
+/// Weighted mean of `values`, using the weights of the rows of `target`.
+fn weighted_mean<T>(target: &impl Target<T>, values: impl Iterator<Item = f64>) -> f64 {
+    let (sum, weight) = values
+        .enumerate()
+        .fold((0., 0.), |(sum, weight), (row, value)| {
+            let w = target.weight(row);
+            (sum + w * value, weight + w)
+        });
+    sum / weight
+}
+
+/// Proportion of rows whose class is predicted. Binary predictions are probabilities of
+/// the positive class, multiclass ones are class labels.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Accuracy;
+
+impl Metric<bool> for Accuracy {
+    fn evaluate(&self, target: &impl Target<bool>, preds: &[f64]) -> f64 {
+        let hits = target
+            .iter()
+            .zip(preds)
+            .map(|(value, pred)| ((*pred >= 0.5) == value) as u8 as f64);
+        weighted_mean(target, hits)
+    }
+    fn higher_is_better(&self) -> bool {
+        true
+    }
+}
+
+impl Metric<u32> for Accuracy {
+    fn evaluate(&self, target: &impl Target<u32>, preds: &[f64]) -> f64 {
+        let hits = target
+            .iter()
+            .zip(preds)
+            .map(|(value, pred)| (*pred == value as f64) as u8 as f64);
+        weighted_mean(target, hits)
+    }
+    fn higher_is_better(&self) -> bool {
+        true
+    }
+}
+
+/// Binary cross entropy, predictions are probabilities of the positive class.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogLoss;
+
+impl Metric<bool> for LogLoss {
+    fn evaluate(&self, target: &impl Target<bool>, preds: &[f64]) -> f64 {
+        let losses = target.iter().zip(preds).map(|(value, pred)| {
+            let pred = pred.clamp(1e-15, 1. - 1e-15);
+            match value {
+                true => -pred.ln(),
+                false => -(1. - pred).ln(),
+            }
+        });
+        weighted_mean(target, losses)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MeanSquaredError;
+
+impl Metric<f64> for MeanSquaredError {
+    fn evaluate(&self, target: &impl Target<f64>, preds: &[f64]) -> f64 {
+        let errors = target
+            .iter()
+            .zip(preds)
+            .map(|(value, pred)| (value - pred).powi(2));
+        weighted_mean(target, errors)
+    }
+}
+
+/// Mean Poisson deviance, predictions are expected counts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PoissonDeviance;
+
+impl Metric<f64> for PoissonDeviance {
+    fn evaluate(&self, target: &impl Target<f64>, preds: &[f64]) -> f64 {
+        let deviances = target.iter().zip(preds).map(|(value, pred)| {
+            let log_ratio = match value {
+                0. => 0.,
+                value => value * (value / pred).ln(),
+            };
+            2. * (log_ratio - value + pred)
+        });
+        weighted_mean(target, deviances)
+    }
+}
+
+/// Mean Gamma deviance, target values and predictions must be positive.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GammaDeviance;
+
+impl Metric<f64> for GammaDeviance {
+    fn evaluate(&self, target: &impl Target<f64>, preds: &[f64]) -> f64 {
+        let deviances = target
+            .iter()
+            .zip(preds)
+            .map(|(value, pred)| 2. * ((pred / value).ln() + value / pred - 1.));
+        weighted_mean(target, deviances)
+    }
+}
+
+/// Mean Tweedie deviance for `1 < variance_power < 2`.
+#[derive(Debug, Clone, Copy)]
+pub struct TweedieDeviance {
+    variance_power: f64,
+}
+
+impl TweedieDeviance {
+    pub fn new(variance_power: f64) -> Self {
+        match variance_power > 1. && variance_power < 2. {
+            true => TweedieDeviance { variance_power },
+            false => panic!("Tweedie variance power must be gt than 1 and lt than 2"),
+        }
+    }
+}
+
+impl Metric<f64> for TweedieDeviance {
+    fn evaluate(&self, target: &impl Target<f64>, preds: &[f64]) -> f64 {
+        let p = self.variance_power;
+        let deviances = target.iter().zip(preds).map(|(value, pred)| {
+            2. * (value.powf(2. - p) / ((1. - p) * (2. - p)) - value * pred.powf(1. - p) / (1. - p)
+                + pred.powf(2. - p) / (2. - p))
+        });
+        weighted_mean(target, deviances)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tree::split::WeightedTarget;
+
+    #[test]
+    fn test_eval_history() {
+        let mut history = EvalHistory::new(false);
+        let stages: Vec<usize> = [3., 2., 2.5, 2., 1.]
+            .into_iter()
+            .enumerate()
+            .map(|(stage, valid)| {
+                history.record(EvalRecord {
+                    stage,
+                    train: 0.,
+                    valid,
+                })
+            })
+            .collect();
+        assert_eq!(vec![0, 0, 1, 2, 0], stages, "Wrong stages since best");
+        assert_eq!(4, history.best_record().unwrap().stage, "Wrong best stage");
+    }
+    #[test]
+    fn test_metrics() {
+        let target = vec![true, false, true, false];
+        assert_eq!(0.75, Accuracy.evaluate(&target, &[0.9, 0.2, 0.6, 0.5]));
+        let weighted = WeightedTarget::new(target, vec![1., 1., 1., 3.]);
+        assert_eq!(0.5, Accuracy.evaluate(&weighted, &[0.9, 0.2, 0.6, 0.5]));
+        let classes: Vec<u32> = vec![0, 2, 1];
+        assert_eq!(2. / 3., Accuracy.evaluate(&classes, &[0., 2., 2.]));
+        let expected = -(0.5f64.ln() + 0.8f64.ln()) / 2.;
+        assert_eq!(expected, LogLoss.evaluate(&vec![true, false], &[0.5, 0.2]));
+        assert_eq!(
+            2.5,
+            MeanSquaredError.evaluate(&vec![1., 2.], &[2., 4.]),
+            "Wrong MSE"
+        );
+    }
+    #[test]
+    fn test_deviances() {
+        let target = vec![0., 2.];
+        let expected = (2. * 1. + 2. * (2. * 2f64.ln() - 1.)) / 2.;
+        let deviance = PoissonDeviance.evaluate(&target, &[1., 1.]);
+        assert!(
+            (deviance - expected).abs() < 1e-12,
+            "Wrong Poisson deviance"
+        );
+        let expected = 2. * (0.5f64.ln() + 1.);
+        let deviance = GammaDeviance.evaluate(&vec![2.], &[1.]);
+        assert!((deviance - expected).abs() < 1e-12, "Wrong Gamma deviance");
+        // Tweedie deviances lie between the Poisson and Gamma ones
+        let (value, pred) = (vec![3.], [2.]);
+        let poisson = PoissonDeviance.evaluate(&value, &pred);
+        let gamma = GammaDeviance.evaluate(&value, &pred);
+        let low = TweedieDeviance::new(1.01).evaluate(&value, &pred);
+        let high = TweedieDeviance::new(1.99).evaluate(&value, &pred);
+        assert!(
+            (low - poisson).abs() < 1e-2,
+            "Tweedie does not tend to Poisson"
+        );
+        assert!(
+            (high - gamma).abs() < 1e-2,
+            "Tweedie does not tend to Gamma"
+        );
+        assert_eq!(0., TweedieDeviance::new(1.5).evaluate(&value, &[3.]));
+    }
+}
@@ -0,0 +1,36 @@
+use std::collections::HashMap;
+
+/// Deterministic pseudo random numbers for the tests, from a linear congruential
+/// generator: fitted models do not depend on the `rand` version.
+pub struct Lcg {
+    state: u64,
+}
+
+impl Lcg {
+    pub fn new(seed: u64) -> Self {
+        Lcg { state: seed }
+    }
+    /// Next number, uniform in `[0, 1)`.
+    pub fn uniform(&mut self) -> f64 {
+        self.state = self
+            .state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.state >> 33) as f64 / (1u64 << 31) as f64
+    }
+}
+
+/// `n_rows` uniform values of `F1` labelled `F1 < 0.5`, a `flipped` fraction of the
+/// labels is flipped.
+pub fn noisy_step(
+    rng: &mut Lcg,
+    n_rows: usize,
+    flipped: f64,
+) -> (HashMap<String, Vec<f64>>, Vec<bool>) {
+    let values: Vec<f64> = (0..n_rows).map(|_| rng.uniform()).collect();
+    let target = values
+        .iter()
+        .map(|v| (*v < 0.5) ^ (rng.uniform() < flipped))
+        .collect();
+    (HashMap::from([("F1".to_string(), values)]), target)
+}
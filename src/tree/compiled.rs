@@ -0,0 +1,249 @@
+use super::loss_fn::split_values::{NullDirection, SplitKind};
+use super::split::{DataSet, DataSetRowsError};
+use super::{leaf_prediction, Tree, TreeError};
+use rayon::iter::{IndexedParallelIterator, ParallelIterator};
+use rayon::slice::ParallelSliceMut;
+use std::borrow::Cow;
+
+/// Rows predicted by a rayon task in `CompiledTree::predict_par_into`.
+pub const PREDICT_CHUNK_ROWS: usize = 4096;
+
+/// A `Tree` flattened into contiguous arrays for inference: node `i` is described by the
+/// `i`-th entry of every array, the root is node 0. Features are resolved to column
+/// indices once, rows are slices of values ordered like `feature_names` and NaN values
+/// are missing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledTree {
+    feature_names: Vec<String>,
+    /// Column index of the feature of every split node.
+    features: Vec<usize>,
+    /// Threshold of numerical splits, category code of equality splits.
+    thresholds: Vec<f64>,
+    /// How present values are routed by every split node.
+    kinds: Vec<SplitKind>,
+    /// Index of the left child, 0 for leaves as the root is nobody's child.
+    left: Vec<usize>,
+    right: Vec<usize>,
+    null_left: Vec<bool>,
+    /// Prediction of every leaf, as returned by `Tree::predict`.
+    leaf_values: Vec<f64>,
+    /// Index of every leaf from left to right, as returned by `Tree::predict_leaf_index`.
+    leaf_slots: Vec<usize>,
+    n_leaves: usize,
+}
+
+impl CompiledTree {
+    /// Flattens `tree`, whose features must all be in `feature_names`.
+    pub fn new(tree: &Tree, feature_names: &[&str]) -> Result<CompiledTree, TreeError> {
+        let mut compiled = CompiledTree {
+            feature_names: feature_names.iter().map(|name| name.to_string()).collect(),
+            features: Vec::new(),
+            thresholds: Vec::new(),
+            kinds: Vec::new(),
+            left: Vec::new(),
+            right: Vec::new(),
+            null_left: Vec::new(),
+            leaf_values: Vec::new(),
+            leaf_slots: Vec::new(),
+            n_leaves: 0,
+        };
+        compiled.push_node(tree)?;
+        Ok(compiled)
+    }
+    /// Appends `node` and its subtree in depth first order, returns the index of `node`.
+    fn push_node(&mut self, node: &Tree) -> Result<usize, TreeError> {
+        let idx = self.features.len();
+        self.left.push(0);
+        self.right.push(0);
+        if let (Some(split_info), Some(l), Some(r)) = (
+            node.split_info.as_ref(),
+            node.left.as_ref(),
+            node.right.as_ref(),
+        ) {
+            let feature = self
+                .feature_names
+                .iter()
+                .position(|name| *name == split_info.name)
+                .ok_or_else(|| TreeError::CouldNotFindFeature(split_info.name.clone()))?;
+            self.features.push(feature);
+            self.thresholds.push(split_info.value);
+            self.kinds.push(split_info.kind.clone());
+            self.null_left
+                .push(split_info.score.null_direction == NullDirection::Left);
+            self.leaf_values.push(0.);
+            self.leaf_slots.push(0);
+            self.left[idx] = self.push_node(l)?;
+            self.right[idx] = self.push_node(r)?;
+        } else {
+            let leaf = node
+                .prediction
+                .as_deref()
+                .ok_or(TreeError::NoPredictionInLeaf)?;
+            self.features.push(0);
+            self.thresholds.push(0.);
+            self.kinds.push(SplitKind::Threshold);
+            self.null_left.push(true);
+            self.leaf_values.push(leaf_prediction(leaf));
+            // Left subtrees are pushed first: leaves are met from left to right
+            self.leaf_slots.push(self.n_leaves);
+            self.n_leaves += 1;
+        }
+        Ok(idx)
+    }
+    pub fn feature_names(&self) -> &[String] {
+        &self.feature_names
+    }
+    pub fn n_nodes(&self) -> usize {
+        self.features.len()
+    }
+    pub fn n_leaves(&self) -> usize {
+        self.n_leaves
+    }
+    /// Index of the leaf reached by a row, `value` returns the value of a column.
+    fn leaf(&self, value: impl Fn(usize) -> f64) -> usize {
+        let mut node = 0;
+        while self.left[node] != 0 {
+            let value = value(self.features[node]);
+            let goes_left = match value.is_nan() {
+                true => self.null_left[node],
+                false => match &self.kinds[node] {
+                    SplitKind::Threshold => value < self.thresholds[node],
+                    SplitKind::Equality => value == self.thresholds[node],
+                    SplitKind::Categories(categories) => categories.goes_left(value as u32),
+                },
+            };
+            node = match goes_left {
+                true => self.left[node],
+                false => self.right[node],
+            };
+        }
+        node
+    }
+    /// Prediction of a row holding the values of `feature_names`.
+    pub fn predict_row(&self, row: &[f64]) -> f64 {
+        self.leaf_values[self.leaf(|feature| row[feature])]
+    }
+    fn check_columns(&self, columns: &[&[f64]], n_rows: usize) {
+        if columns.len() != self.feature_names.len() {
+            panic!(
+                "Got {} columns for {} features",
+                columns.len(),
+                self.feature_names.len()
+            );
+        }
+        if columns.iter().any(|column| column.len() != n_rows) {
+            panic!("Columns must have one value per prediction");
+        }
+    }
+    /// Predictions of the rows of `columns` starting at `first_row`.
+    fn predict_rows(&self, columns: &[&[f64]], first_row: usize, preds: &mut [f64]) {
+        for (offset, pred) in preds.iter_mut().enumerate() {
+            let row = first_row + offset;
+            *pred = self.leaf_values[self.leaf(|feature| columns[feature][row])];
+        }
+    }
+    /// Writes the prediction of every row of `columns`, ordered like `feature_names`, to
+    /// `preds` without allocating.
+    pub fn predict_into(&self, columns: &[&[f64]], preds: &mut [f64]) {
+        self.check_columns(columns, preds.len());
+        self.predict_rows(columns, 0, preds);
+    }
+    /// Same as `predict_into`, chunks of `PREDICT_CHUNK_ROWS` rows are spread across
+    /// the rayon workers.
+    pub fn predict_par_into(&self, columns: &[&[f64]], preds: &mut [f64]) {
+        self.check_columns(columns, preds.len());
+        preds
+            .par_chunks_mut(PREDICT_CHUNK_ROWS)
+            .enumerate()
+            .for_each(|(chunk, preds)| {
+                self.predict_rows(columns, chunk * PREDICT_CHUNK_ROWS, preds)
+            });
+    }
+    /// Writes the index of the leaf reached by every row of `columns` to `leaf_indices`,
+    /// leaves are numbered from left to right. The fast path of `Tree::predict_leaf_index`,
+    /// the tree being compiled once.
+    pub fn predict_leaf_index_into(&self, columns: &[&[f64]], leaf_indices: &mut [usize]) {
+        self.check_columns(columns, leaf_indices.len());
+        for (row, leaf_index) in leaf_indices.iter_mut().enumerate() {
+            *leaf_index = self.leaf_slots[self.leaf(|feature| columns[feature][row])];
+        }
+    }
+    /// Prediction of every row of `columns`, ordered like `feature_names`.
+    pub fn predict(&self, columns: &[&[f64]]) -> Vec<f64> {
+        let mut preds = vec![0.; columns.first().map_or(0, |column| column.len())];
+        self.predict_into(columns, &mut preds);
+        preds
+    }
+    pub fn predict_par(&self, columns: &[&[f64]]) -> Vec<f64> {
+        let mut preds = vec![0.; columns.first().map_or(0, |column| column.len())];
+        self.predict_par_into(columns, &mut preds);
+        preds
+    }
+}
+
+impl Tree {
+    /// Flattens the tree for fast inference on rows ordered like `feature_names`.
+    pub fn compile(&self, feature_names: &[&str]) -> Result<CompiledTree, TreeError> {
+        CompiledTree::new(self, feature_names)
+    }
+    /// Sorted names of the features the tree splits on.
+    pub fn feature_names(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        let mut nodes = vec![self];
+        while let Some(node) = nodes.pop() {
+            if let Some(split_info) = node.split_info.as_ref() {
+                names.push(split_info.name.as_str());
+            }
+            nodes.extend(node.left.as_deref());
+            nodes.extend(node.right.as_deref());
+        }
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+    /// Columns of the features the tree splits on, ordered like `feature_names`. Every
+    /// column must hold the `n_rows` values of the samples.
+    fn feature_columns<'a>(
+        names: &[&str],
+        samples: &'a impl DataSet,
+        n_rows: usize,
+    ) -> Result<Vec<Cow<'a, [f64]>>, TreeError> {
+        names
+            .iter()
+            .map(|name| {
+                let column = samples
+                    .column(name)
+                    .ok_or_else(|| TreeError::CouldNotFindFeature(name.to_string()))?;
+                match column.len() == n_rows {
+                    true => Ok(column),
+                    false => Err(
+                        DataSetRowsError::IllFormedColumn(name.to_string(), column.len()).into(),
+                    ),
+                }
+            })
+            .collect()
+    }
+    /// Same predictions as `predict`: the tree is compiled on the columns of its features,
+    /// which are traversed directly by chunks of rows spread across the rayon workers.
+    pub fn predict_par(&self, samples: &impl DataSet) -> Result<Vec<f64>, TreeError> {
+        let names = self.feature_names();
+        let mut preds = vec![0.; samples.num_rows()?];
+        let columns = Tree::feature_columns(&names, samples, preds.len())?;
+        let columns: Vec<&[f64]> = columns.iter().map(AsRef::as_ref).collect();
+        self.compile(&names)?.predict_par_into(&columns, &mut preds);
+        Ok(preds)
+    }
+    /// Index of the leaf of every sample, leaves are numbered from left to right.
+    /// Built on the `CompiledTree` of `predict_par`, which is compiled again on every
+    /// call: to index several datasets, compile the tree once and call
+    /// `CompiledTree::predict_leaf_index_into`.
+    pub fn predict_leaf_index(&self, samples: &impl DataSet) -> Result<Vec<usize>, TreeError> {
+        let names = self.feature_names();
+        let mut leaf_indices = vec![0; samples.num_rows()?];
+        let columns = Tree::feature_columns(&names, samples, leaf_indices.len())?;
+        let columns: Vec<&[f64]> = columns.iter().map(AsRef::as_ref).collect();
+        self.compile(&names)?
+            .predict_leaf_index_into(&columns, &mut leaf_indices);
+        Ok(leaf_indices)
+    }
+}
@@ -0,0 +1,190 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use std::ops::Range;
+
+use super::loss_fn::{split_values::SplitInfo, Score};
+use super::split::histogram::Histograms;
+use super::split::{partition_rows, DataSet, Target};
+use super::{Tree, TreeBuilder, TreeError};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GrowthPolicy {
+    /// Grow the tree depth first, splitting every node until a stopping criterion is met.
+    #[default]
+    DepthWise,
+    /// Grow the tree best first (LightGBM style): the leaf with the highest gain
+    /// is split until `max_leaf_nodes` is reached.
+    LossGuide,
+}
+
+/// Leaf waiting to be expanded, its rows are already partitioned between its children.
+struct LeafCandidate<St> {
+    gain: f64,
+    node: usize,
+    depth: usize,
+    split_info: SplitInfo,
+    histograms: Histograms<St>,
+    left: Range<usize>,
+    right: Range<usize>,
+}
+
+impl<St> PartialEq for LeafCandidate<St> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<St> Eq for LeafCandidate<St> {}
+
+impl<St> PartialOrd for LeafCandidate<St> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<St> Ord for LeafCandidate<St> {
+    // Highest gain first, ties broken in favour of the oldest node
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.gain
+            .total_cmp(&other.gain)
+            .then_with(|| other.node.cmp(&self.node))
+    }
+}
+
+impl<T, D: DataSet, Tg: Target<T>, S: Score<T>> TreeBuilder<'_, T, D, Tg, S> {
+    fn leaf_candidate(
+        &self,
+        rows: &mut [usize],
+        range: Range<usize>,
+        histograms: Option<Histograms<S::Stats>>,
+        node: usize,
+        depth: usize,
+    ) -> Option<LeafCandidate<S::Stats>> {
+        let node_rows = &mut rows[range.clone()];
+        let node_split = self.node_split(node_rows, histograms, depth)?;
+        let null_direction = node_split.split_info.score.null_direction;
+        let n_left = partition_rows(node_rows, &node_split.mask, null_direction);
+        Some(LeafCandidate {
+            gain: node_split.gain,
+            node,
+            depth,
+            split_info: node_split.split_info,
+            histograms: node_split.histograms,
+            left: range.start..range.start + n_left,
+            right: range.start + n_left..range.end,
+        })
+    }
+    pub(super) fn build_tree_loss_guide(&self, mut rows: Vec<usize>) -> Result<Tree, TreeError> {
+        let mut nodes = vec![Some(self.build_leaf(&rows))];
+        let mut children: Vec<Option<(usize, usize)>> = vec![None];
+        let mut candidates = BinaryHeap::new();
+        let n_rows = rows.len();
+        candidates.extend(self.leaf_candidate(&mut rows, 0..n_rows, None, 0, 0));
+        let mut n_leaves = 1;
+        while self.has_leaf_budget(n_leaves) {
+            let Some(candidate) = candidates.pop() else {
+                break;
+            };
+            if let Some(node) = nodes[candidate.node].as_mut() {
+                node.split_info = Some(candidate.split_info);
+                node.prediction = None;
+            }
+            children[candidate.node] = Some((nodes.len(), nodes.len() + 1));
+            let (left_hist, right_hist) = self.children_histograms(
+                candidate.histograms,
+                &rows[candidate.left.clone()],
+                &rows[candidate.right.clone()],
+                candidate.depth + 1,
+            );
+            for (range, histograms) in [(candidate.left, left_hist), (candidate.right, right_hist)]
+            {
+                let child = nodes.len();
+                nodes.push(Some(self.build_leaf(&rows[range.clone()])));
+                children.push(None);
+                candidates.extend(self.leaf_candidate(
+                    &mut rows,
+                    range,
+                    histograms,
+                    child,
+                    candidate.depth + 1,
+                ));
+            }
+            n_leaves += 1;
+        }
+        // Children are always created after their parent, assemble bottom up
+        for node in (0..nodes.len()).rev() {
+            if let Some((left, right)) = children[node] {
+                let left = nodes[left].take().map(Box::new);
+                let right = nodes[right].take().map(Box::new);
+                if let Some(tree) = nodes[node].as_mut() {
+                    tree.left = left;
+                    tree.right = right;
+                }
+            }
+        }
+        Ok(nodes[0].take().expect("Root is never moved into a parent"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::loss_fn::{Logit, ScoringFunction};
+    use crate::tree::TreeConfig;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_loss_guide_expands_best_leaf() {
+        let data = HashMap::from([(
+            "F1".to_string(),
+            (1..=10).map(|v| v as f64).collect::<Vec<_>>(),
+        )]);
+        let target = vec![
+            false, true, false, false, false, true, true, true, false, false,
+        ];
+        let score_fn = ScoringFunction::Logit(Logit::new(0.5));
+        let fit = |growth_policy| {
+            let tree_config = TreeConfig {
+                max_depth: 10,
+                max_leaf_nodes: Some(3),
+                growth_policy,
+                ..Default::default()
+            };
+            Tree::fit(&data, &target, &tree_config, &score_fn).unwrap()
+        };
+
+        // Depth wise spends the leaf budget on the left child first
+        let depth_wise = fit(GrowthPolicy::DepthWise);
+        assert_eq!(3, depth_wise.n_leaves(), "Wrong number of leaves");
+        assert!(depth_wise.left.unwrap().split_info.is_some());
+
+        // Loss guide splits the child with the highest gain
+        let loss_guide = fit(GrowthPolicy::LossGuide);
+        assert_eq!(3, loss_guide.n_leaves(), "Wrong number of leaves");
+        assert_eq!(6., loss_guide.split_info.as_ref().unwrap().value);
+        assert!(loss_guide.left.unwrap().split_info.is_none());
+        let right_split = loss_guide.right.unwrap().split_info.unwrap();
+        assert_eq!(9., right_split.value, "Wrong split for best leaf");
+    }
+    #[test]
+    fn test_loss_guide_matches_depth_wise_without_budget() {
+        let data = HashMap::from([("F1".to_string(), vec![1., 2., 3., 4., 5., 6.])]);
+        let target = vec![true, false, false, true, true, false];
+        let score_fn = ScoringFunction::Logit(Logit::new(0.5));
+        let depth_wise_config = TreeConfig {
+            max_depth: 4,
+            ..Default::default()
+        };
+        let loss_guide_config = TreeConfig {
+            max_depth: 4,
+            growth_policy: GrowthPolicy::LossGuide,
+            ..Default::default()
+        };
+        assert_eq!(
+            Tree::fit(&data, &target, &depth_wise_config, &score_fn).unwrap(),
+            Tree::fit(&data, &target, &loss_guide_config, &score_fn).unwrap(),
+            "Growth policies should agree when the tree is fully grown"
+        );
+    }
+}
@@ -0,0 +1,421 @@
+use super::split_stats::{GradHessSums, SplitStats};
+use super::split_values::{NullDirection, SplitScore};
+use super::{Score, ScoreError};
+use crate::tree::split::Target;
+
+/// Gradient and hessian of the loss of a single row.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GradHess {
+    pub grad: f64,
+    pub hess: f64,
+}
+
+/// A loss that can be optimized with second order (Newton) steps. Implementing it is
+/// enough to fit trees on the loss with `ObjectiveScore` and to boost them.
+pub trait Objective<T>: Sync {
+    /// Gradient and hessian of the loss for a target value, `raw_pred` is the current
+    /// prediction before the inverse link is applied (log odds for `Logit`).
+    fn grad_and_hess(&self, value: T, raw_pred: f64) -> GradHess;
+    /// Checks that the loss is defined for every value of `target`, called once before
+    /// fitting.
+    fn validate_target(&self, _target: &impl Target<T>) -> Result<(), ScoreError> {
+        Ok(())
+    }
+    /// Raw prediction of every row before any tree is fitted.
+    fn initial_prediction(&self, _target: &impl Target<T>) -> f64 {
+        0.
+    }
+    /// Inverse of the link function, maps a raw prediction to the target space
+    /// (probabilities for `Logit`).
+    fn inverse_link(&self, raw_pred: f64) -> f64 {
+        raw_pred
+    }
+    /// Regularization of the leaves of the trees boosted on the loss.
+    fn regularization(&self) -> Regularization {
+        Regularization::default()
+    }
+    /// Value replacing a leaf of a boosted tree once fitted, from the
+    /// `(value, raw_pred, weight)` of the rows of the leaf, e.g. for losses without
+    /// curvature. `None` keeps the Newton step of the leaf.
+    fn refit_leaf(&self, _rows: impl Iterator<Item = (T, f64, f64)>) -> Option<f64> {
+        None
+    }
+    /// Computes the gradients of every row once, so that trees can be fitted on them
+    /// with `SecondOrder` without going back to the loss.
+    fn gradients(&self, target: &impl Target<T>, raw_preds: &[f64]) -> Vec<GradHess> {
+        target
+            .iter()
+            .zip(raw_preds)
+            .map(|(value, raw_pred)| self.grad_and_hess(value, *raw_pred))
+            .collect()
+    }
+}
+
+/// XGBoost-style regularization of second order leaves, every term defaults to 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Regularization {
+    /// L2 penalty on the leaf weights, added to the hessian sums.
+    pub lambda: f64,
+    /// L1 penalty on the leaf weights, gradient sums are soft-thresholded by it.
+    pub alpha: f64,
+    /// Minimum gain of a split, subtracted from the gain of every split.
+    pub gamma: f64,
+    /// Maximum absolute value of a leaf weight, no clipping if 0.
+    pub max_delta_step: f64,
+}
+
+impl Regularization {
+    pub fn new(lambda: f64, alpha: f64, gamma: f64, max_delta_step: f64) -> Self {
+        if [lambda, alpha, gamma, max_delta_step]
+            .iter()
+            .any(|param| param.is_nan() || *param < 0.)
+        {
+            panic!("Regularization parameters must be gte than 0");
+        }
+        Regularization {
+            lambda,
+            alpha,
+            gamma,
+            max_delta_step,
+        }
+    }
+    /// Gradient sum shrunk towards 0 by `alpha`.
+    fn threshold_grad(&self, grad: f64) -> f64 {
+        grad.signum() * (grad.abs() - self.alpha).max(0.)
+    }
+    /// Leaf weight minimizing the regularized loss, clipped to `max_delta_step`. Rows
+    /// without any curvature (`hess + lambda == 0`) get no step.
+    pub fn leaf_weight(&self, grad: f64, hess: f64) -> f64 {
+        if hess + self.lambda <= 0. {
+            return 0.;
+        }
+        let weight = -self.threshold_grad(grad) / (hess + self.lambda);
+        match self.max_delta_step > 0. {
+            true => weight.clamp(-self.max_delta_step, self.max_delta_step),
+            false => weight,
+        }
+    }
+    /// Decrease of the regularized loss (times 2) when a leaf predicts `leaf_weight`.
+    fn leaf_gain(&self, grad: f64, hess: f64) -> f64 {
+        if hess + self.lambda <= 0. {
+            return 0.;
+        }
+        if self.max_delta_step == 0. {
+            return self.threshold_grad(grad).powi(2) / (hess + self.lambda);
+        }
+        let weight = self.leaf_weight(grad, hess);
+        -(2. * grad * weight
+            + (hess + self.lambda) * weight.powi(2)
+            + 2. * self.alpha * weight.abs())
+    }
+}
+
+/// Gain of a split from the gradient and hessian sums of its left, right and null rows,
+/// null rows are sent to the side with the highest gain.
+pub fn second_order_split_score(
+    left: (f64, f64),
+    right: (f64, f64),
+    null: (f64, f64),
+    regularization: &Regularization,
+) -> Result<SplitScore, ScoreError> {
+    let ((l_g, l_h), (r_g, r_h), (n_g, n_h)) = (left, right, null);
+    let gain = |grad, hess| regularization.leaf_gain(grad, hess);
+    let current = gain(l_g + n_g + r_g, l_h + n_h + r_h) + regularization.gamma;
+    let score_on_left = gain(l_g + n_g, l_h + n_h) + gain(r_g, r_h) - current;
+    let score_on_right = gain(r_g + n_g, r_h + n_h) + gain(l_g, l_h) - current;
+    if score_on_left >= score_on_right {
+        Ok(SplitScore {
+            score: -score_on_left,
+            null_direction: NullDirection::Left,
+        })
+    } else if score_on_right.is_finite() {
+        Ok(SplitScore {
+            score: -score_on_right,
+            null_direction: NullDirection::Right,
+        })
+    } else {
+        Err(ScoreError::NanReturn)
+    }
+}
+
+/// Scores splits of precomputed gradients, leaves hold the (regularized) Newton step
+/// `-G / (H + lambda)`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SecondOrder {
+    regularization: Regularization,
+}
+
+impl SecondOrder {
+    pub fn new(regularization: Regularization) -> Self {
+        SecondOrder { regularization }
+    }
+    pub fn regularization(&self) -> &Regularization {
+        &self.regularization
+    }
+}
+
+impl Score<GradHess> for SecondOrder {
+    type Stats = GradHessSums;
+    fn empty_stats(&self) -> GradHessSums {
+        GradHessSums::default()
+    }
+    fn update_stats(&self, stats: &mut GradHessSums, value: GradHess, weight: f64) {
+        stats.add(value, weight);
+    }
+    fn stats_score(
+        &self,
+        left: &GradHessSums,
+        right: &GradHessSums,
+        null: &GradHessSums,
+    ) -> Result<SplitScore, ScoreError> {
+        let total_len = left.count() + right.count() + null.count();
+        if total_len < 2 {
+            return Err(ScoreError::InvalidSplit(total_len));
+        }
+        second_order_split_score(
+            (left.grad, left.hess),
+            (right.grad, right.hess),
+            (null.grad, null.hess),
+            &self.regularization,
+        )
+    }
+    fn stats_hessian(&self, stats: &GradHessSums) -> f64 {
+        stats.hess
+    }
+    fn pred(&self, target: &impl Target<GradHess>) -> f64 {
+        let sums = self.target_stats(target);
+        self.regularization.leaf_weight(sums.grad, sums.hess)
+    }
+    fn node_score(&self, _target: &impl Target<GradHess>) -> f64 {
+        // split_score is already relative to the unsplit node
+        0.
+    }
+}
+
+/// Scores splits of any `Objective`: the gradients of every row are taken at a constant
+/// raw prediction, leaves hold the (regularized) Newton step from it.
+#[derive(Debug, Clone, Copy)]
+pub struct ObjectiveScore<O> {
+    objective: O,
+    raw_pred: f64,
+    regularization: Regularization,
+}
+
+impl<O> ObjectiveScore<O> {
+    pub fn new(objective: O, raw_pred: f64) -> Self {
+        ObjectiveScore {
+            objective,
+            raw_pred,
+            regularization: Regularization::default(),
+        }
+    }
+    /// Starts from the initial prediction of the objective on `target`.
+    pub fn from_target<T>(objective: O, target: &impl Target<T>) -> Self
+    where
+        O: Objective<T>,
+    {
+        let raw_pred = objective.initial_prediction(target);
+        ObjectiveScore::new(objective, raw_pred)
+    }
+    /// Applies `regularization` to the gain of the splits and to the leaf weights.
+    pub fn with_regularization(self, regularization: Regularization) -> Self {
+        Self {
+            regularization,
+            ..self
+        }
+    }
+    pub fn objective(&self) -> &O {
+        &self.objective
+    }
+    pub fn raw_pred(&self) -> f64 {
+        self.raw_pred
+    }
+}
+
+impl<T, O: Objective<T>> Score<T> for ObjectiveScore<O> {
+    type Stats = GradHessSums;
+    fn empty_stats(&self) -> GradHessSums {
+        GradHessSums::default()
+    }
+    fn update_stats(&self, stats: &mut GradHessSums, value: T, weight: f64) {
+        stats.add(self.objective.grad_and_hess(value, self.raw_pred), weight);
+    }
+    fn validate_target(&self, target: &impl Target<T>) -> Result<(), ScoreError> {
+        self.objective.validate_target(target)
+    }
+    fn stats_score(
+        &self,
+        left: &GradHessSums,
+        right: &GradHessSums,
+        null: &GradHessSums,
+    ) -> Result<SplitScore, ScoreError> {
+        let total_len = left.count() + right.count() + null.count();
+        if total_len < 2 {
+            return Err(ScoreError::InvalidSplit(total_len));
+        }
+        second_order_split_score(
+            (left.grad, left.hess),
+            (right.grad, right.hess),
+            (null.grad, null.hess),
+            &self.regularization,
+        )
+    }
+    fn stats_hessian(&self, stats: &GradHessSums) -> f64 {
+        stats.hess
+    }
+    fn pred(&self, target: &impl Target<T>) -> f64 {
+        let sums = self.target_stats(target);
+        self.regularization.leaf_weight(sums.grad, sums.hess)
+    }
+    fn target_prediction(&self, leaf: f64) -> f64 {
+        self.objective.inverse_link(self.raw_pred + leaf)
+    }
+    fn node_score(&self, _target: &impl Target<T>) -> f64 {
+        // split_score is already relative to the unsplit node
+        0.
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ensemble::{GradientBoosting, GradientBoostingConfig};
+    use crate::tree::loss_fn::{Logit, SquaredError};
+    use crate::tree::{Tree, TreeConfig};
+    use std::collections::HashMap;
+
+    /// Half squared error written as a user supplied objective.
+    #[derive(Clone)]
+    struct HalfSquared;
+
+    impl Objective<f64> for HalfSquared {
+        fn grad_and_hess(&self, value: f64, raw_pred: f64) -> GradHess {
+            GradHess {
+                grad: raw_pred - value,
+                hess: 1.,
+            }
+        }
+        fn initial_prediction(&self, target: &impl Target<f64>) -> f64 {
+            target.iter().sum::<f64>() / target.len() as f64
+        }
+    }
+
+    #[test]
+    fn test_second_order_matches_logit() {
+        let data = HashMap::from([("F1".to_string(), vec![1., 2., 3.])]);
+        let target = vec![true, false, false];
+        let tree_config = TreeConfig {
+            max_depth: 2,
+            ..Default::default()
+        };
+        let logit = Logit::new(0.5);
+        let gradients = logit.gradients(&target, &[0.; 3]);
+        let grad_tree =
+            Tree::fit(&data, &gradients, &tree_config, &SecondOrder::default()).unwrap();
+        let logit_tree = Tree::fit(&data, &target, &tree_config, &logit).unwrap();
+        assert_eq!(logit_tree, grad_tree, "Gradients fit a different tree");
+    }
+    #[test]
+    fn test_regularized_split_score() {
+        // The right child has no hessian: without an L2 penalty it gets no step nor gain
+        let (left, right, null) = ((-1., 0.5), (0.5, 0.), (0., 0.));
+        let score =
+            second_order_split_score(left, right, null, &Regularization::default()).unwrap();
+        assert_eq!(
+            -(1. / 0.5 - 0.25 / 0.5),
+            score.score,
+            "Wrong unpenalized score"
+        );
+        assert_eq!(0., Regularization::default().leaf_weight(0.5, 0.));
+        let regularization = Regularization::new(1., 0., 0., 0.);
+        let score = second_order_split_score(left, right, null, &regularization).unwrap();
+        let expected = 1. / 1.5 + 0.25 / 1. - 0.25 / 1.5;
+        assert!((score.score + expected).abs() < 1e-12, "Wrong split score");
+
+        // Clipped leaves gain less than the unconstrained Newton step
+        let clipped = Regularization::new(1., 0., 0., 0.1);
+        let score = second_order_split_score(left, right, null, &clipped).unwrap();
+        let gain = |g: f64, h: f64| {
+            let w = (-g / (h + 1.)).clamp(-0.1, 0.1);
+            -(2. * g * w + (h + 1.) * w * w)
+        };
+        let expected = gain(-1., 0.5) + gain(0.5, 0.) - gain(-0.5, 0.5);
+        assert!(
+            (score.score + expected).abs() < 1e-12,
+            "Wrong clipped score"
+        );
+    }
+    #[test]
+    fn test_objective_score_matches_logit() {
+        let data = HashMap::from([("F1".to_string(), vec![1., 2., 3., 4., 5.])]);
+        let target = vec![true, false, false, true, true];
+        let tree_config = TreeConfig {
+            max_depth: 3,
+            ..Default::default()
+        };
+        let regularization = Regularization::new(1., 0.1, 0., 0.5);
+        let logit = Logit::new(0.5).with_regularization(regularization);
+        let objective =
+            ObjectiveScore::new(Logit::new(0.5), 0.).with_regularization(regularization);
+        let logit_tree = Tree::fit(&data, &target, &tree_config, &logit).unwrap();
+        let objective_tree = Tree::fit(&data, &target, &tree_config, &objective).unwrap();
+        assert_eq!(
+            logit_tree, objective_tree,
+            "Objective fits a different tree"
+        );
+    }
+    #[test]
+    fn test_custom_objective() {
+        let data = HashMap::from([("F1".to_string(), vec![1., 2., 3., 4., 5., 6.])]);
+        let target = vec![1., 1.5, 2., 7., 8., 9.];
+        let tree_config = TreeConfig {
+            max_depth: 1,
+            ..Default::default()
+        };
+        let score = ObjectiveScore::from_target(HalfSquared, &target);
+        assert_eq!(4.75, score.raw_pred(), "Wrong initial prediction");
+        let tree = Tree::fit(&data, &target, &tree_config, &score).unwrap();
+        let squared_tree = Tree::fit(&data, &target, &tree_config, &SquaredError).unwrap();
+        let split = tree.split_info.as_ref().unwrap();
+        assert_eq!(squared_tree.split_info.as_ref().unwrap().value, split.value);
+        // Leaves are the Newton steps from the initial prediction: the means of the children
+        for (step, mean) in tree
+            .predict(&data)
+            .unwrap()
+            .into_iter()
+            .zip(squared_tree.predict(&data).unwrap())
+        {
+            assert!((score.raw_pred() + step - mean).abs() < 1e-12, "Wrong leaf");
+        }
+
+        let config = GradientBoostingConfig {
+            n_estimators: 1,
+            learning_rate: 1.,
+            tree_config,
+            ..Default::default()
+        };
+        let model = GradientBoosting::fit(&data, &target, &config, &HalfSquared).unwrap();
+        assert_eq!(score.raw_pred(), model.base_score, "Wrong base score");
+        assert_eq!(
+            squared_tree.predict(&data).unwrap(),
+            model.predict(&data).unwrap()
+        );
+    }
+    #[test]
+    fn test_gradients_use_raw_predictions() {
+        let logit = Logit::new(0.5);
+        let gradients = logit.gradients(&vec![true, false], &[0., 2f64.ln()]);
+        assert_eq!(
+            GradHess {
+                grad: -0.5,
+                hess: 0.25
+            },
+            gradients[0]
+        );
+        assert!(
+            (gradients[1].grad - 2. / 3.).abs() < 1e-12,
+            "Wrong gradient"
+        );
+        assert!((gradients[1].hess - 2. / 9.).abs() < 1e-12, "Wrong hessian");
+    }
+}
@@ -0,0 +1,260 @@
+use super::gradients::{GradHess, Objective};
+use super::ScoreError;
+use crate::tree::split::{check_target_values, Target};
+
+/// Weighted mean of `target`.
+fn weighted_mean(target: &impl Target<f64>) -> f64 {
+    let (sum, weight) = target
+        .iter_weighted()
+        .fold((0., 0.), |(sum, total), (value, weight)| {
+            (sum + weight * value, total + weight)
+        });
+    sum / weight
+}
+
+/// Log of the weighted mean of `target`: the best constant raw prediction of every
+/// log-link objective, finite for the targets they validate.
+fn log_weighted_mean(target: &impl Target<f64>) -> f64 {
+    weighted_mean(target).ln()
+}
+
+/// Checks that every value of `target` is non negative and that their mean is positive,
+/// so that the log of the mean is finite.
+fn validate_non_negative(target: &impl Target<f64>) -> Result<(), ScoreError> {
+    check_target_values(target.iter(), |value| value >= 0., "gte than 0")?;
+    match weighted_mean(target) > 0. {
+        true => Ok(()),
+        false => Err(ScoreError::NonPositiveTargetMean),
+    }
+}
+
+/// Poisson regression of counts, raw predictions are the log of the expected count.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Poisson;
+
+impl Objective<f64> for Poisson {
+    fn grad_and_hess(&self, value: f64, raw_pred: f64) -> GradHess {
+        let mean = raw_pred.exp();
+        GradHess {
+            grad: mean - value,
+            hess: mean,
+        }
+    }
+    fn validate_target(&self, target: &impl Target<f64>) -> Result<(), ScoreError> {
+        validate_non_negative(target)
+    }
+    fn initial_prediction(&self, target: &impl Target<f64>) -> f64 {
+        log_weighted_mean(target)
+    }
+    fn inverse_link(&self, raw_pred: f64) -> f64 {
+        raw_pred.exp()
+    }
+}
+
+/// Gamma regression of positive amounts (e.g. claim severities), raw predictions are
+/// the log of the expected value.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Gamma;
+
+impl Objective<f64> for Gamma {
+    fn grad_and_hess(&self, value: f64, raw_pred: f64) -> GradHess {
+        let ratio = value * (-raw_pred).exp();
+        GradHess {
+            grad: 1. - ratio,
+            hess: ratio,
+        }
+    }
+    /// Zero targets would have no curvature: every value must be positive.
+    fn validate_target(&self, target: &impl Target<f64>) -> Result<(), ScoreError> {
+        check_target_values(target.iter(), |value| value > 0., "gt than 0")
+    }
+    fn initial_prediction(&self, target: &impl Target<f64>) -> f64 {
+        log_weighted_mean(target)
+    }
+    fn inverse_link(&self, raw_pred: f64) -> f64 {
+        raw_pred.exp()
+    }
+}
+
+/// Tweedie regression of non negative amounts with an atom at 0 (e.g. total claims),
+/// a compound Poisson-Gamma distribution for `1 < variance_power < 2`.
+#[derive(Debug, Clone, Copy)]
+pub struct Tweedie {
+    variance_power: f64,
+}
+
+impl Tweedie {
+    pub fn new(variance_power: f64) -> Self {
+        match variance_power > 1. && variance_power < 2. {
+            true => Tweedie { variance_power },
+            false => panic!("Tweedie variance power must be gt than 1 and lt than 2"),
+        }
+    }
+    pub fn variance_power(&self) -> f64 {
+        self.variance_power
+    }
+}
+
+impl Objective<f64> for Tweedie {
+    fn grad_and_hess(&self, value: f64, raw_pred: f64) -> GradHess {
+        let p = self.variance_power;
+        let (lower, upper) = (((1. - p) * raw_pred).exp(), ((2. - p) * raw_pred).exp());
+        GradHess {
+            grad: upper - value * lower,
+            hess: (2. - p) * upper - (1. - p) * value * lower,
+        }
+    }
+    fn validate_target(&self, target: &impl Target<f64>) -> Result<(), ScoreError> {
+        validate_non_negative(target)
+    }
+    fn initial_prediction(&self, target: &impl Target<f64>) -> f64 {
+        log_weighted_mean(target)
+    }
+    fn inverse_link(&self, raw_pred: f64) -> f64 {
+        raw_pred.exp()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ensemble::{GradientBoosting, GradientBoostingConfig};
+    use crate::metrics::{GammaDeviance, Metric, PoissonDeviance, TweedieDeviance};
+    use crate::tree::loss_fn::gradients::ObjectiveScore;
+    use crate::tree::split::{NonNegativeTarget, WeightedTarget};
+    use crate::tree::{Tree, TreeConfig, TreeError};
+    use std::collections::HashMap;
+
+    type Deviance<'a> = &'a dyn Fn(&[f64]) -> f64;
+
+    /// Sum of the gradients of `target` at `raw_pred`.
+    fn grad_sum(objective: &impl Objective<f64>, target: &[f64], raw_pred: f64) -> f64 {
+        target
+            .iter()
+            .map(|value| objective.grad_and_hess(*value, raw_pred).grad)
+            .sum()
+    }
+
+    #[test]
+    fn test_gradients() {
+        let (value, raw_pred, eps) = (3., 0.4, 1e-4);
+        // Losses up to terms that do not depend on the raw prediction
+        let poisson = |raw: f64| raw.exp() - value * raw;
+        let gamma = |raw: f64| value * (-raw).exp() + raw;
+        let tweedie = |raw: f64| -value * (-0.5 * raw).exp() / -0.5 + (0.5 * raw).exp() / 0.5;
+        let objectives: [(&dyn Fn(f64) -> f64, GradHess); 3] = [
+            (&poisson, Poisson.grad_and_hess(value, raw_pred)),
+            (&gamma, Gamma.grad_and_hess(value, raw_pred)),
+            (&tweedie, Tweedie::new(1.5).grad_and_hess(value, raw_pred)),
+        ];
+        for (loss, grad_hess) in objectives {
+            let grad = (loss(raw_pred + eps) - loss(raw_pred - eps)) / (2. * eps);
+            let hess =
+                (loss(raw_pred + eps) - 2. * loss(raw_pred) + loss(raw_pred - eps)) / eps.powi(2);
+            assert!((grad - grad_hess.grad).abs() < 1e-6, "Wrong gradient");
+            assert!((hess - grad_hess.hess).abs() < 1e-6, "Wrong hessian");
+        }
+    }
+    #[test]
+    fn test_closed_form_leaves() {
+        let target = NonNegativeTarget::new(vec![0., 1., 2., 5., 0., 4.]).unwrap();
+        let values = target.values();
+        // The log of the mean zeroes the gradients of every log-link objective
+        let raw_pred = Poisson.initial_prediction(&target);
+        assert_eq!(2f64.ln(), raw_pred, "Wrong initial prediction");
+        assert!(grad_sum(&Poisson, values, raw_pred).abs() < 1e-12);
+        assert!(grad_sum(&Tweedie::new(1.3), values, raw_pred).abs() < 1e-12);
+        let positive = [0.5, 1., 2., 4.5];
+        assert!(grad_sum(&Gamma, &positive, 2f64.ln()).abs() < 1e-12);
+
+        let weighted =
+            WeightedTarget::new(NonNegativeTarget::new(vec![1., 4.]).unwrap(), vec![2., 1.]);
+        assert_eq!(2f64.ln(), Gamma.initial_prediction(&weighted));
+    }
+    #[test]
+    fn test_boosted_leaves_converge_to_group_means() {
+        // A single split is possible: every tree splits the two groups
+        let data = HashMap::from([("F1".to_string(), vec![1., 1., 1., 2., 2., 2.])]);
+        let target = NonNegativeTarget::new(vec![1., 2., 3., 8., 10., 12.]).unwrap();
+        let config = GradientBoostingConfig {
+            n_estimators: 50,
+            learning_rate: 0.5,
+            tree_config: TreeConfig {
+                max_depth: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let expected = [2., 2., 2., 10., 10., 10.];
+        let check = |preds: Vec<f64>| {
+            for (pred, mean) in preds.iter().zip(expected) {
+                assert!((pred - mean).abs() < 1e-6, "Leaf {} is not {}", pred, mean);
+            }
+        };
+        let poisson = GradientBoosting::fit(&data, &target, &config, &Poisson).unwrap();
+        check(poisson.predict(&data).unwrap());
+        let gamma = GradientBoosting::fit(&data, &target, &config, &Gamma).unwrap();
+        check(gamma.predict(&data).unwrap());
+        let tweedie = Tweedie::new(1.5);
+        let tweedie_model = GradientBoosting::fit(&data, &target, &config, &tweedie).unwrap();
+        let preds = tweedie_model.predict(&data).unwrap();
+        check(preds.clone());
+
+        // Group means minimize the deviances
+        let shifted: Vec<f64> = expected.iter().map(|mean| mean * 1.1).collect();
+        let deviances: [Deviance; 3] = [
+            &|preds| PoissonDeviance.evaluate(&target, preds),
+            &|preds| GammaDeviance.evaluate(&target, preds),
+            &|preds| TweedieDeviance::new(1.5).evaluate(&target, preds),
+        ];
+        for deviance in deviances {
+            assert!(
+                deviance(&preds) < deviance(&shifted),
+                "Deviance not minimal"
+            );
+            assert!(
+                deviance(&preds) < deviance(&[6.; 6]),
+                "Deviance not minimal"
+            );
+        }
+    }
+    #[test]
+    fn test_invalid_targets() {
+        let data = HashMap::from([("F1".to_string(), vec![1., 2., 3.])]);
+        let config = GradientBoostingConfig::default();
+        fn invalid<L>(result: Result<GradientBoosting<L>, TreeError>) -> ScoreError {
+            match result {
+                Err(TreeError::ScoreError(err)) => err,
+                _ => panic!("Target should be invalid"),
+            }
+        }
+        let zero = vec![0., 1., 2.];
+        assert!(matches!(
+            invalid(GradientBoosting::fit(&data, &zero, &config, &Gamma)),
+            ScoreError::InvalidTargetValue { value, .. } if value == 0.
+        ));
+        assert!(GradientBoosting::fit(&data, &zero, &config, &Poisson).is_ok());
+        let negative = vec![1., -1., 2.];
+        assert!(matches!(
+            invalid(GradientBoosting::fit(&data, &negative, &config, &Tweedie::new(1.5))),
+            ScoreError::InvalidTargetValue { value, .. } if value == -1.
+        ));
+        assert!(matches!(
+            invalid(GradientBoosting::fit(
+                &data,
+                &vec![0.; 3],
+                &config,
+                &Poisson
+            )),
+            ScoreError::NonPositiveTargetMean
+        ));
+        let score = ObjectiveScore::new(Gamma, 0.);
+        assert!(Tree::fit(&data, &zero, &config.tree_config, &score).is_err());
+        assert!(NonNegativeTarget::new(negative).is_err());
+    }
+    #[test]
+    #[should_panic]
+    fn test_invalid_variance_power() {
+        Tweedie::new(2.);
+    }
+}
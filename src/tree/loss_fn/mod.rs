@@ -1,8 +1,19 @@
+pub mod gradients;
+pub mod log_link;
+pub mod multiclass;
+pub mod quantile;
+pub mod regression;
+pub mod split_stats;
 pub mod split_values;
 
 use core::cmp::Ordering;
-use std::collections::HashMap;
 
+use gradients::{second_order_split_score, GradHess, Objective, Regularization};
+pub use log_link::{Gamma, Poisson, Tweedie};
+pub use multiclass::{MulticlassEntropy, MulticlassGini, Softmax};
+pub use quantile::Quantile;
+pub use regression::{AbsoluteError, Huber, RegressionScore, SquaredError};
+use split_stats::{ClassCounts, SplitStats};
 use split_values::{NullDirection, SplitScore};
 
 use super::split::Target;
@@ -15,27 +26,98 @@ pub enum ScoreError {
     PerfectSplit,
     #[error("CAnnot split target of len {0}")]
     InvalidSplit(usize),
+    #[error("Label {label} out of range for {n_classes} classes")]
+    LabelOutOfRange { label: u32, n_classes: usize },
+    #[error("Target value {value} is not {expected}")]
+    InvalidTargetValue { value: f64, expected: &'static str },
+    #[error("Target mean must be gt than 0")]
+    NonPositiveTargetMean,
 }
 
 pub trait Score<T>: Sync {
+    type Stats: SplitStats;
+    fn empty_stats(&self) -> Self::Stats;
+    fn update_stats(&self, stats: &mut Self::Stats, value: T, weight: f64);
+    /// Checks that every value of `target` can be fitted, called once before fitting.
+    fn validate_target(&self, _target: &impl Target<T>) -> Result<(), ScoreError> {
+        Ok(())
+    }
+    /// Statistics of all the rows of `target`.
+    fn target_stats(&self, target: &impl Target<T>) -> Self::Stats {
+        let mut stats = self.empty_stats();
+        for (value, weight) in target.iter_weighted() {
+            self.update_stats(&mut stats, value, weight);
+        }
+        stats
+    }
+    /// Scores a split from the statistics of its left, right and null rows.
+    fn stats_score(
+        &self,
+        left: &Self::Stats,
+        right: &Self::Stats,
+        null: &Self::Stats,
+    ) -> Result<split_values::SplitScore, ScoreError>;
+    /// Hessian sum of the rows in `stats`, used to enforce `min_child_weight`.
+    fn stats_hessian(&self, stats: &Self::Stats) -> f64 {
+        stats.weight()
+    }
+    fn pred(&self, target: &impl Target<T>) -> f64;
+    /// Prediction in the target space of a leaf holding `leaf`, e.g. the probability
+    /// reached by the log odds step of a `Logit` leaf.
+    fn target_prediction(&self, leaf: f64) -> f64 {
+        leaf
+    }
+    /// Values stored in a leaf, scores predicting several outputs (e.g. class
+    /// probabilities) return one value per output.
+    fn leaf_value(&self, target: &impl Target<T>) -> Vec<f64> {
+        vec![self.pred(target)]
+    }
+    /// Whether leaves hold class probabilities: the probability of `true` for `bool`
+    /// targets, one probability per class otherwise.
+    fn probability_leaves(&self) -> bool {
+        false
+    }
+    /// Score of the node left unsplit, on the same scale as `split_score`:
+    /// the gain of a split is `node_score - split_score`.
+    fn node_score(&self, target: &impl Target<T>) -> f64;
+    fn mask_stats(
+        &self,
+        target: &impl Target<T>,
+        filter_mask: impl Iterator<Item = Option<bool>>,
+    ) -> (Self::Stats, Self::Stats, Self::Stats) {
+        let mut left = self.empty_stats();
+        let mut right = self.empty_stats();
+        let mut null = self.empty_stats();
+        for ((val, weight), mask) in target.iter_weighted().zip(filter_mask) {
+            match mask {
+                Some(true) => self.update_stats(&mut left, val, weight),
+                Some(false) => self.update_stats(&mut right, val, weight),
+                None => self.update_stats(&mut null, val, weight),
+            }
+        }
+        (left, right, null)
+    }
     fn split_score(
         &self,
         target: &impl Target<T>,
         filter_mask: impl Iterator<Item = Option<bool>>,
-    ) -> Result<split_values::SplitScore, ScoreError>;
-    fn pred(&self, target: &impl Target<T>) -> f64;
+    ) -> Result<split_values::SplitScore, ScoreError> {
+        let (left, right, null) = self.mask_stats(target, filter_mask);
+        self.stats_score(&left, &right, &null)
+    }
 }
 
 pub struct Gini;
 
 impl Gini {
-    fn gini(counts: &HashMap<bool, usize>, total: f64) -> f64 {
+    fn gini(counts: &ClassCounts) -> f64 {
+        let total = counts.total();
         if total == 0.0 {
             return 0.0;
         }
 
-        let sum_of_squares = counts.values().fold(0.0, |acc, &count| {
-            let proportion = count as f64 / total;
+        let sum_of_squares = counts.counts().iter().fold(0.0, |acc, &count| {
+            let proportion = count / total;
             acc + proportion * proportion
         });
 
@@ -44,177 +126,330 @@ impl Gini {
 
     fn impurity(
         &self,
-        left_counts: &HashMap<bool, usize>,
-        right_counts: &HashMap<bool, usize>,
-        null_counts: &HashMap<bool, usize>,
-        left_total: f64,
-        right_total: f64,
-        null_total: f64,
+        left_counts: &ClassCounts,
+        right_counts: &ClassCounts,
+        null_counts: &ClassCounts,
     ) -> split_values::SplitScore {
-        let mut left_with_nulls = left_counts.clone();
-        for (&label, &count) in null_counts {
-            *left_with_nulls.entry(label).or_insert(0) += count;
-        }
-        let left_gini_with_nulls = Gini::gini(&left_with_nulls, left_total + null_total);
+        weighted_impurity(left_counts, right_counts, null_counts, Gini::gini)
+    }
+}
 
-        let mut right_with_nulls = right_counts.clone();
-        for (&label, &count) in null_counts {
-            *right_with_nulls.entry(label).or_insert(0) += count;
-        }
-        let right_gini_with_nulls = Gini::gini(&right_with_nulls, right_total + null_total);
-
-        let total = left_total + right_total + null_total;
-        let weighted_left_gini = (left_total + null_total) / total * left_gini_with_nulls
-            + right_total / total * Gini::gini(right_counts, right_total);
-        let weighted_right_gini = left_total / total * Gini::gini(left_counts, left_total)
-            + (right_total + null_total) / total * right_gini_with_nulls;
-
-        match weighted_left_gini
-            .partial_cmp(&weighted_right_gini)
-            .expect("Cannot Compare")
-        {
-            Ordering::Less | Ordering::Equal => split_values::SplitScore {
-                score: weighted_left_gini,
-                null_direction: NullDirection::Left,
-            },
-            _ => split_values::SplitScore {
-                score: weighted_right_gini,
-                null_direction: NullDirection::Right,
-            },
-        }
+/// Entropy (in bits) of the label distribution of `counts`.
+fn entropy(counts: &ClassCounts) -> f64 {
+    distribution_entropy(counts.counts())
+}
+
+/// Entropy (in bits) of the distribution with the given (unnormalized) weights.
+fn distribution_entropy(weights: &[f64]) -> f64 {
+    let total: f64 = weights.iter().sum();
+    if total == 0.0 {
+        return 0.0;
+    }
+    weights
+        .iter()
+        .filter(|weight| **weight > 0.)
+        .fold(0.0, |acc, &weight| {
+            let proportion = weight / total;
+            acc - proportion * proportion.log2()
+        })
+}
+
+/// Impurity of the children weighted by their size, null rows are sent
+/// to the side with the lowest weighted impurity.
+fn weighted_impurity(
+    left_counts: &ClassCounts,
+    right_counts: &ClassCounts,
+    null_counts: &ClassCounts,
+    impurity: impl Fn(&ClassCounts) -> f64,
+) -> split_values::SplitScore {
+    let left_total = left_counts.total();
+    let right_total = right_counts.total();
+    let null_total = null_counts.total();
+
+    let mut left_with_nulls = left_counts.clone();
+    left_with_nulls.merge(null_counts);
+    let left_impurity_with_nulls = impurity(&left_with_nulls);
+
+    let mut right_with_nulls = right_counts.clone();
+    right_with_nulls.merge(null_counts);
+    let right_impurity_with_nulls = impurity(&right_with_nulls);
+
+    let total = left_total + right_total + null_total;
+    let weighted_left_impurity = (left_total + null_total) / total * left_impurity_with_nulls
+        + right_total / total * impurity(right_counts);
+    let weighted_right_impurity = left_total / total * impurity(left_counts)
+        + (right_total + null_total) / total * right_impurity_with_nulls;
+
+    match weighted_left_impurity
+        .partial_cmp(&weighted_right_impurity)
+        .expect("Cannot Compare")
+    {
+        Ordering::Less | Ordering::Equal => split_values::SplitScore {
+            score: weighted_left_impurity,
+            null_direction: NullDirection::Left,
+        },
+        _ => split_values::SplitScore {
+            score: weighted_right_impurity,
+            null_direction: NullDirection::Right,
+        },
     }
 }
 
 impl Score<bool> for Gini {
-    fn split_score(
+    type Stats = ClassCounts;
+    fn empty_stats(&self) -> ClassCounts {
+        ClassCounts::new(2)
+    }
+    fn update_stats(&self, stats: &mut ClassCounts, value: bool, weight: f64) {
+        stats.add(value as usize, weight);
+    }
+    fn stats_score(
         &self,
-        target: &impl Target<bool>,
-        filter_mask: impl Iterator<Item = Option<bool>>,
+        left: &ClassCounts,
+        right: &ClassCounts,
+        null: &ClassCounts,
     ) -> Result<split_values::SplitScore, ScoreError> {
-        let mut left_counts: HashMap<bool, usize> = HashMap::new();
-        let mut right_counts: HashMap<bool, usize> = HashMap::new();
-        let mut null_counts: HashMap<bool, usize> = HashMap::new();
+        let total_len = left.count() + right.count() + null.count();
+        if total_len == left.count() || total_len == right.count() || total_len == null.count() {
+            Err(ScoreError::PerfectSplit)
+        } else {
+            Ok(self.impurity(left, right, null))
+        }
+    }
+    fn probability_leaves(&self) -> bool {
+        true
+    }
+    fn pred(&self, target: &impl Target<bool>) -> f64 {
+        true_proportion(&self.target_stats(target))
+    }
+    fn node_score(&self, target: &impl Target<bool>) -> f64 {
+        Gini::gini(&self.target_stats(target))
+    }
+}
 
-        let mut left_total = 0.0;
-        let mut right_total = 0.0;
-        let mut null_total = 0.0;
+fn true_proportion(counts: &ClassCounts) -> f64 {
+    counts.counts()[1] / counts.total()
+}
 
-        for (val, mask) in target.iter().zip(filter_mask) {
-            match mask {
-                Some(true) => {
-                    *left_counts.entry(val).or_insert(0) += 1;
-                    left_total += 1.;
-                }
-                Some(false) => {
-                    *right_counts.entry(val).or_insert(0) += 1;
-                    right_total += 1.;
-                }
-                None => {
-                    *null_counts.entry(val).or_insert(0) += 1;
-                    null_total += 1.;
-                }
-            }
-        }
-        let total_len = left_total + right_total + null_total;
-        if total_len == left_total || total_len == right_total || total_len == null_total {
+/// Information gain: splits minimize the weighted entropy of the children.
+pub struct Entropy;
+
+impl Score<bool> for Entropy {
+    type Stats = ClassCounts;
+    fn empty_stats(&self) -> ClassCounts {
+        ClassCounts::new(2)
+    }
+    fn update_stats(&self, stats: &mut ClassCounts, value: bool, weight: f64) {
+        stats.add(value as usize, weight);
+    }
+    fn stats_score(
+        &self,
+        left: &ClassCounts,
+        right: &ClassCounts,
+        null: &ClassCounts,
+    ) -> Result<SplitScore, ScoreError> {
+        let total_len = left.count() + right.count() + null.count();
+        if total_len == left.count() || total_len == right.count() || total_len == null.count() {
             Err(ScoreError::PerfectSplit)
         } else {
-            Ok(self.impurity(
-                &left_counts,
-                &right_counts,
-                &null_counts,
-                left_total,
-                right_total,
-                null_total,
-            ))
+            Ok(weighted_impurity(left, right, null, entropy))
         }
     }
+    fn probability_leaves(&self) -> bool {
+        true
+    }
     fn pred(&self, target: &impl Target<bool>) -> f64 {
-        let (true_cnt, len) = target.iter().fold((0., 0.), |(tc, l), v| {
-            let add_cnt = if v { 1. } else { 0. };
-            (tc + add_cnt, l + 1.)
-        });
-        true_cnt / len
+        true_proportion(&self.target_stats(target))
+    }
+    fn node_score(&self, target: &impl Target<bool>) -> f64 {
+        entropy(&self.target_stats(target))
+    }
+}
+
+/// C4.5 gain ratio: the information gain of a split divided by the entropy of the
+/// children sizes, which penalizes splits isolating a handful of rows.
+pub struct GainRatio;
+
+impl GainRatio {
+    fn gain_ratio(parent_entropy: f64, left: &ClassCounts, right: &ClassCounts) -> f64 {
+        let (left_total, right_total) = (left.total(), right.total());
+        let total = left_total + right_total;
+        let children_entropy =
+            left_total / total * entropy(left) + right_total / total * entropy(right);
+        match distribution_entropy(&[left_total, right_total]) {
+            0. => 0.,
+            split_info => (parent_entropy - children_entropy) / split_info,
+        }
     }
 }
 
+impl Score<bool> for GainRatio {
+    type Stats = ClassCounts;
+    fn empty_stats(&self) -> ClassCounts {
+        ClassCounts::new(2)
+    }
+    fn update_stats(&self, stats: &mut ClassCounts, value: bool, weight: f64) {
+        stats.add(value as usize, weight);
+    }
+    fn stats_score(
+        &self,
+        left: &ClassCounts,
+        right: &ClassCounts,
+        null: &ClassCounts,
+    ) -> Result<SplitScore, ScoreError> {
+        let total_len = left.count() + right.count() + null.count();
+        if total_len == left.count() || total_len == right.count() || total_len == null.count() {
+            return Err(ScoreError::PerfectSplit);
+        }
+        let mut left_with_nulls = left.clone();
+        left_with_nulls.merge(null);
+        let mut right_with_nulls = right.clone();
+        right_with_nulls.merge(null);
+        let mut parent = left_with_nulls.clone();
+        parent.merge(right);
+        let parent_entropy = entropy(&parent);
+
+        let ratio_on_left = GainRatio::gain_ratio(parent_entropy, &left_with_nulls, right);
+        let ratio_on_right = GainRatio::gain_ratio(parent_entropy, left, &right_with_nulls);
+        // The ratio is not relative to the node, scores are negated ratios and the node scores 0
+        if ratio_on_left >= ratio_on_right {
+            Ok(SplitScore {
+                score: -ratio_on_left,
+                null_direction: NullDirection::Left,
+            })
+        } else {
+            Ok(SplitScore {
+                score: -ratio_on_right,
+                null_direction: NullDirection::Right,
+            })
+        }
+    }
+    fn probability_leaves(&self) -> bool {
+        true
+    }
+    fn pred(&self, target: &impl Target<bool>) -> f64 {
+        true_proportion(&self.target_stats(target))
+    }
+    fn node_score(&self, _target: &impl Target<bool>) -> f64 {
+        0.
+    }
+}
+
+/// Logistic loss of a single tree fitted from the fixed prior probability `pred`: the
+/// gradient and hessian of every row are taken at the prior, so the stats of a node are
+/// its label counts. Boosting takes them at the raw prediction of every row instead,
+/// through the `Objective` impl and `SecondOrder`.
 #[derive(Copy, Clone)]
 pub struct Logit {
     pred: f64,
+    regularization: Regularization,
 }
 
 impl Logit {
     pub fn new(pred: f64) -> Self {
         match pred > 0. && pred < 1. {
-            true => Self { pred },
+            true => Self {
+                pred,
+                regularization: Regularization::default(),
+            },
             false => panic!("Initial prediction for Logit must be gt than 0 a lt than 1"),
         }
     }
+    /// Applies `regularization` to the gain of the splits and to the leaf weights.
+    pub fn with_regularization(self, regularization: Regularization) -> Self {
+        Self {
+            regularization,
+            ..self
+        }
+    }
+    /// Gradient and hessian of a label at the prior probability.
     fn grad_and_hes(&self, target: bool) -> (f64, f64) {
         let target_val = if target { 1. } else { 0. };
         let grad = self.pred - target_val;
         let hess = self.pred * (1.0 - self.pred);
         (grad, hess)
     }
+    /// Gradient and hessian sums of a set of labels: both only depend on the label counts.
+    fn stats_grad_and_hes(&self, counts: &ClassCounts) -> (f64, f64) {
+        let (false_g, false_h) = self.grad_and_hes(false);
+        let (true_g, true_h) = self.grad_and_hes(true);
+        let (false_cnt, true_cnt) = (counts.counts()[0], counts.counts()[1]);
+        (
+            false_cnt * false_g + true_cnt * true_g,
+            false_cnt * false_h + true_cnt * true_h,
+        )
+    }
 }
 
 impl Score<bool> for Logit {
-    fn split_score(
+    type Stats = ClassCounts;
+    fn empty_stats(&self) -> ClassCounts {
+        ClassCounts::new(2)
+    }
+    fn update_stats(&self, stats: &mut ClassCounts, value: bool, weight: f64) {
+        stats.add(value as usize, weight);
+    }
+    fn stats_score(
         &self,
-        target: &impl Target<bool>,
-        filter_mask: impl Iterator<Item = Option<bool>>,
+        left: &ClassCounts,
+        right: &ClassCounts,
+        null: &ClassCounts,
     ) -> Result<SplitScore, ScoreError> {
-        if target.len() < 2 {
-            return Err(ScoreError::InvalidSplit(target.len()));
-        }
-        let mut l_g = 0.;
-        let mut l_h = 0.;
-        let mut r_g = 0.;
-        let mut r_h = 0.;
-        let mut n_g = 0.;
-        let mut n_h = 0.;
-        for (bool_v, m) in target.iter().zip(filter_mask) {
-            let (gv, hv) = self.grad_and_hes(bool_v);
-            if let Some(true) = m {
-                l_g += gv;
-                l_h += hv;
-            } else if let Some(false) = m {
-                r_g += gv;
-                r_h += hv;
-            } else {
-                n_g += gv;
-                n_h += hv;
-            }
-        }
-        let current = (l_g + n_g + r_g).powi(2) / (l_h + n_h + r_h);
-        let score_on_left = (l_g + n_g).powi(2) / (l_h + n_h) + r_g.powi(2) / r_h - current;
-        let score_on_right = (r_g + n_g).powi(2) / (r_h + n_h) + l_g.powi(2) / l_h - current;
-        if score_on_left >= score_on_right {
-            Ok(SplitScore {
-                score: -score_on_left,
-                null_direction: NullDirection::Left,
-            })
-        } else if score_on_right.is_finite() {
-            Ok(SplitScore {
-                score: -score_on_right,
-                null_direction: NullDirection::Right,
-            })
-        } else {
-            Err(ScoreError::NanReturn)
+        let total_len = left.count() + right.count() + null.count();
+        if total_len < 2 {
+            return Err(ScoreError::InvalidSplit(total_len));
         }
+        second_order_split_score(
+            self.stats_grad_and_hes(left),
+            self.stats_grad_and_hes(right),
+            self.stats_grad_and_hes(null),
+            &self.regularization,
+        )
+    }
+    fn stats_hessian(&self, stats: &ClassCounts) -> f64 {
+        self.stats_grad_and_hes(stats).1
     }
     fn pred(&self, target: &impl Target<bool>) -> f64 {
-        let (g, h) = target.iter().fold((0., 0.), |(g, h), v| {
-            let (vg, vh) = self.grad_and_hes(v);
-            (g + vg, h + vh)
-        });
-        -g / h
+        let (g, h) = self.stats_grad_and_hes(&self.target_stats(target));
+        self.regularization.leaf_weight(g, h)
+    }
+    fn target_prediction(&self, leaf: f64) -> f64 {
+        self.inverse_link((self.pred / (1. - self.pred)).ln() + leaf)
+    }
+    fn node_score(&self, _target: &impl Target<bool>) -> f64 {
+        // split_score is already relative to the unsplit node
+        0.
     }
 }
 
+impl Objective<bool> for Logit {
+    fn grad_and_hess(&self, value: bool, raw_pred: f64) -> GradHess {
+        let target_val = if value { 1. } else { 0. };
+        let pred = self.inverse_link(raw_pred);
+        GradHess {
+            grad: pred - target_val,
+            hess: pred * (1. - pred),
+        }
+    }
+    /// Log odds of the initial prediction.
+    fn initial_prediction(&self, _target: &impl Target<bool>) -> f64 {
+        (self.pred / (1. - self.pred)).ln()
+    }
+    fn inverse_link(&self, raw_pred: f64) -> f64 {
+        1. / (1. + (-raw_pred).exp())
+    }
+    fn regularization(&self) -> Regularization {
+        self.regularization
+    }
+}
+
+/// Classification score functions selectable at runtime, see `RegressionScore` for
+/// `f64` targets.
 pub enum ScoringFunction {
     Logit(Logit),
     Gini(Gini),
+    Entropy(Entropy),
+    GainRatio(GainRatio),
 }
 
 impl std::fmt::Display for ScoringFunction {
@@ -222,26 +457,82 @@ impl std::fmt::Display for ScoringFunction {
         let displayable = match self {
             ScoringFunction::Logit(_) => "Logit",
             ScoringFunction::Gini(_) => "Gini",
+            ScoringFunction::Entropy(_) => "Entropy",
+            ScoringFunction::GainRatio(_) => "GainRatio",
         };
         write!(f, "{}", displayable)
     }
 }
 
 impl Score<bool> for ScoringFunction {
-    fn split_score(
+    type Stats = ClassCounts;
+    fn empty_stats(&self) -> ClassCounts {
+        match self {
+            ScoringFunction::Gini(g) => g.empty_stats(),
+            ScoringFunction::Logit(l) => l.empty_stats(),
+            ScoringFunction::Entropy(e) => e.empty_stats(),
+            ScoringFunction::GainRatio(g) => g.empty_stats(),
+        }
+    }
+    fn update_stats(&self, stats: &mut ClassCounts, value: bool, weight: f64) {
+        match self {
+            ScoringFunction::Gini(g) => g.update_stats(stats, value, weight),
+            ScoringFunction::Logit(l) => l.update_stats(stats, value, weight),
+            ScoringFunction::Entropy(e) => e.update_stats(stats, value, weight),
+            ScoringFunction::GainRatio(g) => g.update_stats(stats, value, weight),
+        }
+    }
+    fn stats_score(
         &self,
-        target: &impl Target<bool>,
-        filter_mask: impl Iterator<Item = Option<bool>>,
+        left: &ClassCounts,
+        right: &ClassCounts,
+        null: &ClassCounts,
     ) -> Result<SplitScore, ScoreError> {
         match self {
-            ScoringFunction::Gini(g) => g.split_score(target, filter_mask),
-            ScoringFunction::Logit(l) => l.split_score(target, filter_mask),
+            ScoringFunction::Gini(g) => g.stats_score(left, right, null),
+            ScoringFunction::Logit(l) => l.stats_score(left, right, null),
+            ScoringFunction::Entropy(e) => e.stats_score(left, right, null),
+            ScoringFunction::GainRatio(g) => g.stats_score(left, right, null),
+        }
+    }
+    fn stats_hessian(&self, stats: &ClassCounts) -> f64 {
+        match self {
+            ScoringFunction::Gini(g) => g.stats_hessian(stats),
+            ScoringFunction::Logit(l) => l.stats_hessian(stats),
+            ScoringFunction::Entropy(e) => e.stats_hessian(stats),
+            ScoringFunction::GainRatio(g) => g.stats_hessian(stats),
         }
     }
     fn pred(&self, target: &impl Target<bool>) -> f64 {
         match self {
             ScoringFunction::Gini(g) => g.pred(target),
             ScoringFunction::Logit(l) => l.pred(target),
+            ScoringFunction::Entropy(e) => e.pred(target),
+            ScoringFunction::GainRatio(g) => g.pred(target),
+        }
+    }
+    fn probability_leaves(&self) -> bool {
+        match self {
+            ScoringFunction::Gini(g) => g.probability_leaves(),
+            ScoringFunction::Logit(l) => l.probability_leaves(),
+            ScoringFunction::Entropy(e) => e.probability_leaves(),
+            ScoringFunction::GainRatio(g) => g.probability_leaves(),
+        }
+    }
+    fn target_prediction(&self, leaf: f64) -> f64 {
+        match self {
+            ScoringFunction::Gini(g) => g.target_prediction(leaf),
+            ScoringFunction::Logit(l) => l.target_prediction(leaf),
+            ScoringFunction::Entropy(e) => e.target_prediction(leaf),
+            ScoringFunction::GainRatio(g) => g.target_prediction(leaf),
+        }
+    }
+    fn node_score(&self, target: &impl Target<bool>) -> f64 {
+        match self {
+            ScoringFunction::Gini(g) => g.node_score(target),
+            ScoringFunction::Logit(l) => l.node_score(target),
+            ScoringFunction::Entropy(e) => e.node_score(target),
+            ScoringFunction::GainRatio(g) => g.node_score(target),
         }
     }
 }
@@ -249,6 +540,10 @@ impl Score<bool> for ScoringFunction {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::tree::split::DataSet;
+    use crate::tree::TreeConfig;
+    use std::collections::HashMap;
+
     #[test]
     fn test_stuff() {
         let init_prd: f64 = 0.5;
@@ -259,4 +554,67 @@ mod test {
         assert_eq!(g, g_res, "Wrong grad for Logit");
         assert_eq!(h, h_res, "Wrong hess for Logit");
     }
+    #[test]
+    fn test_logit_regularization() {
+        let target = vec![true, true, false];
+        // G = 3 * 0.5 - 2, H = 3 * 0.25
+        assert_eq!(0.5 / 0.75, Logit::new(0.5).pred(&target));
+        let regularized =
+            Logit::new(0.5).with_regularization(Regularization::new(1., 0.25, 0., 0.));
+        assert_eq!(0.25 / 1.75, regularized.pred(&target), "Wrong leaf weight");
+        let clipped = Logit::new(0.5).with_regularization(Regularization::new(0., 0., 0., 0.1));
+        assert_eq!(0.1, clipped.pred(&target), "Leaf weight not clipped");
+
+        let data = HashMap::from([("f1".to_owned(), vec![1., 2., 3., 4.])]);
+        let tar = vec![true, true, false, false];
+        let tree_config = TreeConfig::default();
+        let (split, _) = data
+            .find_best_split(&tar, &Logit::new(0.5), &tree_config)
+            .unwrap();
+        let gamma = Regularization::new(0., 0., 0.5, 0.);
+        let (pruned, _) = data
+            .find_best_split(
+                &tar,
+                &Logit::new(0.5).with_regularization(gamma),
+                &tree_config,
+            )
+            .unwrap();
+        assert_eq!(
+            split.score.score + 0.5,
+            pruned.score.score,
+            "Gamma not applied"
+        );
+        let tree = crate::tree::Tree::fit(
+            &data,
+            &tar,
+            &tree_config,
+            &Logit::new(0.5).with_regularization(Regularization::new(0., 0., 4., 0.)),
+        )
+        .unwrap();
+        assert!(tree.split_info.is_none(), "Gamma should prune the split");
+    }
+    #[test]
+    fn test_entropy_and_gain_ratio() {
+        let data = HashMap::from([("f1".to_owned(), vec![1., 2., 3., 4., 5., 6.])]);
+        let tar = vec![false, false, false, true, false, true];
+        let tree_config = TreeConfig::default();
+        let parent_entropy = distribution_entropy(&[4., 2.]);
+
+        let (info_gain, _) = data
+            .find_best_split(&tar, &ScoringFunction::Entropy(Entropy), &tree_config)
+            .unwrap();
+        // {F, F, F} vs {T, F, T}: half of the entropy of a 1/3 split
+        assert_eq!(4., info_gain.value, "Wrong entropy split");
+        let expected = distribution_entropy(&[1., 2.]) / 2.;
+        assert!((info_gain.score.score - expected).abs() < 1e-12);
+
+        let (gain_ratio, _) = data
+            .find_best_split(&tar, &ScoringFunction::GainRatio(GainRatio), &tree_config)
+            .unwrap();
+        // {F, F, F, T, F} vs {T}: the unbalanced split wins once its gain is normalized
+        assert_eq!(6., gain_ratio.value, "Wrong gain ratio split");
+        let children_entropy = 5. / 6. * distribution_entropy(&[4., 1.]);
+        let expected = (parent_entropy - children_entropy) / distribution_entropy(&[5., 1.]);
+        assert!((gain_ratio.score.score + expected).abs() < 1e-12);
+    }
 }
@@ -0,0 +1,307 @@
+use super::gradients::{GradHess, Regularization};
+use super::split_stats::{ClassCounts, SplitStats};
+use super::split_values::SplitScore;
+use super::{entropy, weighted_impurity, Gini, Score, ScoreError};
+use crate::tree::split::Target;
+
+fn proportions(counts: &ClassCounts) -> Vec<f64> {
+    let total = counts.total();
+    counts.counts().iter().map(|count| count / total).collect()
+}
+
+fn check_n_classes(n_classes: usize) {
+    if n_classes < 2 {
+        panic!(
+            "Multiclass scores need at least 2 classes, got {}",
+            n_classes
+        );
+    }
+}
+
+/// Checks that every label of `target` is in `0..n_classes`.
+pub(crate) fn check_labels(target: &impl Target<u32>, n_classes: usize) -> Result<(), ScoreError> {
+    match target.iter().find(|label| *label as usize >= n_classes) {
+        Some(label) => Err(ScoreError::LabelOutOfRange { label, n_classes }),
+        None => Ok(()),
+    }
+}
+
+fn impurity_split_score(
+    left: &ClassCounts,
+    right: &ClassCounts,
+    null: &ClassCounts,
+    impurity: impl Fn(&ClassCounts) -> f64,
+) -> Result<SplitScore, ScoreError> {
+    let total_len = left.count() + right.count() + null.count();
+    if total_len == left.count() || total_len == right.count() || total_len == null.count() {
+        Err(ScoreError::PerfectSplit)
+    } else {
+        Ok(weighted_impurity(left, right, null, impurity))
+    }
+}
+
+/// Gini impurity over class labels in `0..n_classes`, leaves hold the class probabilities.
+#[derive(Debug, Clone, Copy)]
+pub struct MulticlassGini {
+    n_classes: usize,
+}
+
+impl MulticlassGini {
+    pub fn new(n_classes: usize) -> Self {
+        check_n_classes(n_classes);
+        MulticlassGini { n_classes }
+    }
+}
+
+impl Score<u32> for MulticlassGini {
+    type Stats = ClassCounts;
+    fn empty_stats(&self) -> ClassCounts {
+        ClassCounts::new(self.n_classes)
+    }
+    fn update_stats(&self, stats: &mut ClassCounts, value: u32, weight: f64) {
+        stats.add(value as usize, weight);
+    }
+    fn validate_target(&self, target: &impl Target<u32>) -> Result<(), ScoreError> {
+        check_labels(target, self.n_classes)
+    }
+    fn stats_score(
+        &self,
+        left: &ClassCounts,
+        right: &ClassCounts,
+        null: &ClassCounts,
+    ) -> Result<SplitScore, ScoreError> {
+        impurity_split_score(left, right, null, Gini::gini)
+    }
+    /// Most frequent class.
+    fn pred(&self, target: &impl Target<u32>) -> f64 {
+        crate::tree::argmax(&self.leaf_value(target)) as f64
+    }
+    fn leaf_value(&self, target: &impl Target<u32>) -> Vec<f64> {
+        proportions(&self.target_stats(target))
+    }
+    fn probability_leaves(&self) -> bool {
+        true
+    }
+    fn node_score(&self, target: &impl Target<u32>) -> f64 {
+        Gini::gini(&self.target_stats(target))
+    }
+}
+
+/// Entropy over class labels in `0..n_classes`, the gain of a split is its information gain.
+/// Leaves hold the class probabilities.
+#[derive(Debug, Clone, Copy)]
+pub struct MulticlassEntropy {
+    n_classes: usize,
+}
+
+impl MulticlassEntropy {
+    pub fn new(n_classes: usize) -> Self {
+        check_n_classes(n_classes);
+        MulticlassEntropy { n_classes }
+    }
+}
+
+impl Score<u32> for MulticlassEntropy {
+    type Stats = ClassCounts;
+    fn empty_stats(&self) -> ClassCounts {
+        ClassCounts::new(self.n_classes)
+    }
+    fn update_stats(&self, stats: &mut ClassCounts, value: u32, weight: f64) {
+        stats.add(value as usize, weight);
+    }
+    fn validate_target(&self, target: &impl Target<u32>) -> Result<(), ScoreError> {
+        check_labels(target, self.n_classes)
+    }
+    fn stats_score(
+        &self,
+        left: &ClassCounts,
+        right: &ClassCounts,
+        null: &ClassCounts,
+    ) -> Result<SplitScore, ScoreError> {
+        impurity_split_score(left, right, null, entropy)
+    }
+    /// Most frequent class.
+    fn pred(&self, target: &impl Target<u32>) -> f64 {
+        crate::tree::argmax(&self.leaf_value(target)) as f64
+    }
+    fn leaf_value(&self, target: &impl Target<u32>) -> Vec<f64> {
+        proportions(&self.target_stats(target))
+    }
+    fn probability_leaves(&self) -> bool {
+        true
+    }
+    fn node_score(&self, target: &impl Target<u32>) -> f64 {
+        entropy(&self.target_stats(target))
+    }
+}
+
+/// Multinomial log loss over class labels in `0..n_classes`. `MulticlassBoosting` fits
+/// one tree per class on the gradients of its raw score, probabilities are their softmax.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Softmax {
+    n_classes: usize,
+    regularization: Regularization,
+}
+
+impl Softmax {
+    pub fn new(n_classes: usize) -> Self {
+        check_n_classes(n_classes);
+        Softmax {
+            n_classes,
+            regularization: Regularization::default(),
+        }
+    }
+    /// Applies `regularization` to the gain of the splits and to the leaf weights.
+    pub fn with_regularization(self, regularization: Regularization) -> Self {
+        Self {
+            regularization,
+            ..self
+        }
+    }
+    pub fn n_classes(&self) -> usize {
+        self.n_classes
+    }
+    pub fn regularization(&self) -> Regularization {
+        self.regularization
+    }
+    /// Raw score of every class before any tree is fitted: the log of its weighted
+    /// frequency, which softmax maps back to the frequencies.
+    pub fn initial_predictions(&self, target: &impl Target<u32>) -> Vec<f64> {
+        let mut counts = ClassCounts::new(self.n_classes);
+        for (label, weight) in target.iter_weighted() {
+            counts.add(label as usize, weight);
+        }
+        proportions(&counts)
+            .into_iter()
+            .map(|proportion| proportion.max(f64::MIN_POSITIVE).ln())
+            .collect()
+    }
+    /// Class probabilities from the raw scores of a row.
+    pub fn probabilities(&self, raw_preds: &[f64]) -> Vec<f64> {
+        let max = raw_preds.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let exps: Vec<f64> = raw_preds.iter().map(|raw| (raw - max).exp()).collect();
+        let sum: f64 = exps.iter().sum();
+        exps.iter().map(|exp| exp / sum).collect()
+    }
+    /// Gradients of every row for each class, `raw_preds[row]` holds the raw score
+    /// of every class. `gradients[class]` is the target of the tree of that class.
+    pub fn gradients(
+        &self,
+        target: &impl Target<u32>,
+        raw_preds: &[Vec<f64>],
+    ) -> Vec<Vec<GradHess>> {
+        let mut gradients = vec![Vec::with_capacity(target.len()); self.n_classes];
+        for (class, raw_pred) in target.iter().zip(raw_preds) {
+            let probabilities = self.probabilities(raw_pred);
+            for (k, p) in probabilities.into_iter().enumerate() {
+                let target_val = if k == class as usize { 1. } else { 0. };
+                gradients[k].push(GradHess {
+                    grad: p - target_val,
+                    hess: p * (1. - p),
+                });
+            }
+        }
+        gradients
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tree::loss_fn::gradients::SecondOrder;
+    use crate::tree::split::DataSet;
+    use crate::tree::{Tree, TreeConfig};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_multiclass_split() {
+        let data = HashMap::from([("f1".to_owned(), vec![1., 2., 3., 4., 5., 6.])]);
+        let tar: Vec<u32> = vec![0, 0, 1, 1, 2, 2];
+        let tree_config = TreeConfig::default();
+        let (gini, _) = data
+            .find_best_split(&tar, &MulticlassGini::new(3), &tree_config)
+            .unwrap();
+        // {0, 0} vs {1, 1, 2, 2}: 4 / 6 * 0.5
+        assert_eq!(3., gini.value, "Wrong Gini split");
+        assert!(
+            (gini.score.score - 1. / 3.).abs() < 1e-12,
+            "Wrong Gini score"
+        );
+        let (entropy, _) = data
+            .find_best_split(&tar, &MulticlassEntropy::new(3), &tree_config)
+            .unwrap();
+        // {0, 0} vs {1, 1, 2, 2}: 4 / 6 * 1 bit
+        assert_eq!(3., entropy.value, "Wrong entropy split");
+        assert!(
+            (entropy.score.score - 2. / 3.).abs() < 1e-12,
+            "Wrong entropy score"
+        );
+    }
+    #[test]
+    fn test_multiclass_tree() {
+        let data = HashMap::from([("f1".to_owned(), vec![1., 2., 3., 4., 5., 6.])]);
+        let tar: Vec<u32> = vec![0, 0, 1, 1, 2, 0];
+        let tree_config = TreeConfig {
+            max_depth: 1,
+            ..Default::default()
+        };
+        let tree = Tree::fit(&data, &tar, &tree_config, &MulticlassGini::new(3)).unwrap();
+        let probabilities = tree.predict_vector(&data).unwrap();
+        assert_eq!(vec![1., 0., 0.], probabilities[0], "Wrong pure leaf");
+        assert_eq!(vec![0.25, 0.5, 0.25], probabilities[5], "Wrong mixed leaf");
+        assert_eq!(
+            vec![0., 0., 1., 1., 1., 1.],
+            tree.predict(&data).unwrap(),
+            "Leaves should predict their most frequent class"
+        );
+    }
+    #[test]
+    fn test_label_out_of_range() {
+        let data = HashMap::from([("f1".to_owned(), vec![1., 2., 3.])]);
+        let tar: Vec<u32> = vec![0, 1, 3];
+        let result = Tree::fit(&data, &tar, &TreeConfig::default(), &MulticlassGini::new(3));
+        assert!(matches!(
+            result,
+            Err(crate::tree::TreeError::ScoreError(
+                ScoreError::LabelOutOfRange {
+                    label: 3,
+                    n_classes: 3
+                }
+            ))
+        ));
+        assert!(check_labels(&tar, 4).is_ok());
+    }
+    #[test]
+    fn test_softmax_trees() {
+        let data = HashMap::from([("f1".to_owned(), vec![1., 2., 3., 4., 5., 6.])]);
+        let tar: Vec<u32> = vec![0, 0, 1, 1, 2, 2];
+        let softmax = Softmax::new(3);
+        let gradients = softmax.gradients(&tar, &vec![vec![0.; 3]; tar.len()]);
+        assert_eq!(3, gradients.len(), "One gradient vector per class");
+        for row in 0..tar.len() {
+            let grad_sum: f64 = gradients.iter().map(|class| class[row].grad).sum();
+            assert!(grad_sum.abs() < 1e-12, "Gradients should sum to 0");
+        }
+        let tree_config = TreeConfig {
+            max_depth: 2,
+            ..Default::default()
+        };
+        let raw_preds: Vec<Vec<f64>> = gradients
+            .iter()
+            .map(|class| {
+                let tree = Tree::fit(&data, class, &tree_config, &SecondOrder::default()).unwrap();
+                tree.predict(&data).unwrap()
+            })
+            .collect();
+        for (row, class) in tar.iter().enumerate() {
+            let row_preds: Vec<f64> = raw_preds.iter().map(|preds| preds[row]).collect();
+            let probabilities = softmax.probabilities(&row_preds);
+            assert_eq!(
+                class as usize,
+                crate::tree::argmax(&probabilities),
+                "Wrong class for row {}",
+                row
+            );
+        }
+    }
+}
@@ -0,0 +1,397 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+
+use super::gradients::{GradHess, Objective};
+use super::regression::{min_loss_split, validate_finite};
+use super::split_stats::ValueStats;
+use super::split_values::SplitScore;
+use super::{Score, ScoreError};
+use crate::tree::split::Target;
+
+/// Weight, weighted sum and weighted sum of squares of a set of values.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Moments {
+    pub weight: f64,
+    pub sum: f64,
+    pub sum_sq: f64,
+}
+
+impl Moments {
+    fn add(&mut self, value: f64, weight: f64) {
+        self.weight += weight;
+        self.sum += weight * value;
+        self.sum_sq += weight * value * value;
+    }
+    pub fn minus(self, other: &Moments) -> Moments {
+        Moments {
+            weight: self.weight - other.weight,
+            sum: self.sum - other.sum,
+            sum_sq: self.sum_sq - other.sum_sq,
+        }
+    }
+}
+
+/// Orders `(value, weight)` pairs by value, then weight, so that equal pairs are adjacent.
+fn pair_cmp(a: &(f64, f64), b: &(f64, f64)) -> Ordering {
+    a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1))
+}
+
+/// `(value, weight)` pairs of a set of rows. Adding a pair is constant, the pairs are
+/// sorted when first merged or queried, merging and subtracting sorted pairs is linear.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WeightedValues {
+    pairs: Vec<(f64, f64)>,
+    sorted: bool,
+}
+
+impl WeightedValues {
+    pub fn add(&mut self, value: f64, weight: f64) {
+        self.sorted = match self.pairs.last() {
+            Some(last) => self.sorted && pair_cmp(last, &(value, weight)).is_le(),
+            None => true,
+        };
+        self.pairs.push((value, weight));
+    }
+    fn sort(&mut self) {
+        if !self.sorted {
+            self.pairs.sort_by(pair_cmp);
+            self.sorted = true;
+        }
+    }
+    fn sorted_pairs(&self) -> Cow<'_, [(f64, f64)]> {
+        match self.sorted {
+            true => Cow::Borrowed(&self.pairs),
+            false => {
+                let mut pairs = self.pairs.clone();
+                pairs.sort_by(pair_cmp);
+                Cow::Owned(pairs)
+            }
+        }
+    }
+    pub fn merge(&mut self, other: &WeightedValues) {
+        self.sort();
+        let other = other.sorted_pairs();
+        let mut merged = Vec::with_capacity(self.pairs.len() + other.len());
+        let (mut i, mut j) = (0, 0);
+        while i < self.pairs.len() && j < other.len() {
+            if pair_cmp(&self.pairs[i], &other[j]).is_le() {
+                merged.push(self.pairs[i]);
+                i += 1;
+            } else {
+                merged.push(other[j]);
+                j += 1;
+            }
+        }
+        merged.extend_from_slice(&self.pairs[i..]);
+        merged.extend_from_slice(&other[j..]);
+        self.pairs = merged;
+    }
+    /// Removes the pairs of `other`, the ones missing from `self` are ignored.
+    pub fn subtract(&mut self, other: &WeightedValues) {
+        self.sort();
+        let other = other.sorted_pairs();
+        let mut j = 0;
+        self.pairs.retain(|pair| {
+            while j < other.len() && pair_cmp(&other[j], pair).is_lt() {
+                j += 1;
+            }
+            let removed = j < other.len() && pair_cmp(&other[j], pair).is_eq();
+            j += removed as usize;
+            !removed
+        });
+    }
+    /// Moments of all the values.
+    pub fn total(&self) -> Moments {
+        self.moments(|_| true)
+    }
+    /// Moments of the values lower than `value`.
+    pub fn below(&self, value: f64) -> Moments {
+        self.moments(|v| v < value)
+    }
+    fn moments(&self, keep: impl Fn(f64) -> bool) -> Moments {
+        self.pairs.iter().filter(|(v, _)| keep(*v)).fold(
+            Moments::default(),
+            |mut moments, (v, w)| {
+                moments.add(*v, *w);
+                moments
+            },
+        )
+    }
+    /// Smallest value whose cumulative weight reaches `alpha` of the total weight, a
+    /// minimizer of the pinball loss. `None` without values.
+    pub fn quantile(&self, alpha: f64) -> Option<f64> {
+        let pairs = self.sorted_pairs();
+        let target = alpha * pairs.iter().map(|(_, w)| w).sum::<f64>();
+        let mut cumulative = 0.;
+        pairs
+            .iter()
+            .filter(|(_, w)| *w > 0.)
+            .find(|(_, w)| {
+                cumulative += w;
+                cumulative >= target
+            })
+            .or(pairs.last())
+            .map(|(value, _)| *value)
+    }
+    /// Weighted median: when the weights of the values below and above a gap are equal,
+    /// the middle of the gap. `None` without values.
+    pub fn median(&self) -> Option<f64> {
+        let pairs = self.sorted_pairs();
+        let half = pairs.iter().map(|(_, w)| w).sum::<f64>() / 2.;
+        let mut cumulative = 0.;
+        for (idx, (value, weight)) in pairs.iter().enumerate() {
+            cumulative += weight;
+            if cumulative > half {
+                return Some(*value);
+            }
+            if cumulative == half {
+                return match pairs[idx + 1..].iter().find(|(_, w)| *w > 0.) {
+                    Some((next, _)) => Some((value + next) / 2.),
+                    None => Some(*value),
+                };
+            }
+        }
+        pairs.last().map(|(value, _)| *value)
+    }
+}
+
+/// Pinball loss of `values` when predicting `leaf`: values below the leaf weigh
+/// `1 - alpha`, the other ones `alpha`. The absolute error for `alpha = 0.5`, halved.
+pub(super) fn pinball_loss(values: &WeightedValues, leaf: f64, alpha: f64) -> f64 {
+    let below = values.below(leaf);
+    let above = values.total().minus(&below);
+    alpha * (above.sum - leaf * above.weight) + (1. - alpha) * (leaf * below.weight - below.sum)
+}
+
+/// Weighted `alpha`-quantile of `(value, weight)` pairs, NaN without pairs.
+fn weighted_quantile(pairs: impl Iterator<Item = (f64, f64)>, alpha: f64) -> f64 {
+    let mut values = WeightedValues::default();
+    pairs.for_each(|(value, weight)| values.add(value, weight));
+    values.quantile(alpha).unwrap_or(f64::NAN)
+}
+
+/// Quantile regression: splits minimize the pinball loss, leaves hold the weighted
+/// `alpha`-quantile of the target. When boosted, trees are fitted on the gradients of
+/// the pinball loss and their leaves hold the `alpha`-quantile of the residuals.
+#[derive(Debug, Clone, Copy)]
+pub struct Quantile {
+    pub alpha: f64,
+}
+
+impl Quantile {
+    pub fn new(alpha: f64) -> Self {
+        match alpha > 0. && alpha < 1. {
+            true => Quantile { alpha },
+            false => panic!("Quantile alpha must be gt than 0 and lt than 1"),
+        }
+    }
+    fn loss(&self, parts: &[&ValueStats]) -> f64 {
+        let values = ValueStats::merged_values(parts);
+        values
+            .quantile(self.alpha)
+            .map_or(0., |leaf| pinball_loss(&values, leaf, self.alpha))
+    }
+}
+
+impl Objective<f64> for Quantile {
+    fn grad_and_hess(&self, value: f64, raw_pred: f64) -> GradHess {
+        let grad = match value < raw_pred {
+            true => 1. - self.alpha,
+            false => -self.alpha,
+        };
+        GradHess { grad, hess: 1. }
+    }
+    fn validate_target(&self, target: &impl Target<f64>) -> Result<(), ScoreError> {
+        validate_finite(target)
+    }
+    fn initial_prediction(&self, target: &impl Target<f64>) -> f64 {
+        let values = (0..target.len()).map(|row| (target.value(row), target.weight(row)));
+        weighted_quantile(values, self.alpha)
+    }
+    /// The pinball loss has no curvature: leaves hold the `alpha`-quantile of the
+    /// residuals of their rows instead of a Newton step.
+    fn refit_leaf(&self, rows: impl Iterator<Item = (f64, f64, f64)>) -> Option<f64> {
+        let residuals = rows.map(|(value, raw_pred, weight)| (value - raw_pred, weight));
+        Some(weighted_quantile(residuals, self.alpha))
+    }
+}
+
+impl Score<f64> for Quantile {
+    type Stats = ValueStats;
+    fn empty_stats(&self) -> ValueStats {
+        ValueStats::with_values()
+    }
+    fn update_stats(&self, stats: &mut ValueStats, value: f64, weight: f64) {
+        stats.add(value, weight);
+    }
+    fn validate_target(&self, target: &impl Target<f64>) -> Result<(), ScoreError> {
+        validate_finite(target)
+    }
+    fn stats_score(
+        &self,
+        left: &ValueStats,
+        right: &ValueStats,
+        null: &ValueStats,
+    ) -> Result<SplitScore, ScoreError> {
+        min_loss_split(left, right, null, |parts| self.loss(parts))
+    }
+    fn pred(&self, target: &impl Target<f64>) -> f64 {
+        ValueStats::merged_values(&[&self.target_stats(target)])
+            .quantile(self.alpha)
+            .unwrap_or(f64::NAN)
+    }
+    fn node_score(&self, target: &impl Target<f64>) -> f64 {
+        self.loss(&[&self.target_stats(target)])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ensemble::{GradientBoosting, GradientBoostingConfig};
+    use crate::test_utils::Lcg;
+    use crate::tree::split::WeightedTarget;
+    use crate::tree::{Tree, TreeConfig};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_weighted_values() {
+        let values = |pairs: &[(f64, f64)]| {
+            let mut values = WeightedValues::default();
+            pairs.iter().for_each(|(v, w)| values.add(*v, *w));
+            values
+        };
+        let bins = [
+            values(&[(3., 1.), (1., 2.)]),
+            values(&[(7., 1.), (3., 1.), (-2., 0.5)]),
+            values(&[(5., 3.), (1., 2.)]),
+        ];
+        let mut total = WeightedValues::default();
+        bins.iter().for_each(|bin| total.merge(bin));
+        let all = values(&[
+            (3., 1.),
+            (1., 2.),
+            (7., 1.),
+            (3., 1.),
+            (-2., 0.5),
+            (5., 3.),
+            (1., 2.),
+        ]);
+        assert_eq!(
+            all.quantile(0.3),
+            total.quantile(0.3),
+            "Wrong merged values"
+        );
+        total.subtract(&bins[1]);
+        let mut expected = bins[0].clone();
+        expected.merge(&bins[2]);
+        assert_eq!(expected, total, "Wrong subtracted values");
+        // (2, 1) was never added, only one (1, 2) pair is removed
+        total.subtract(&values(&[(2., 1.), (1., 2.)]));
+        let remaining = values(&[(1., 2.), (3., 1.), (5., 3.)]);
+        assert_eq!(
+            remaining, total,
+            "Wrong values after subtracting missing ones"
+        );
+        let below = total.below(4.);
+        assert_eq!((3., 5.), (below.weight, below.sum), "Wrong moments below 4");
+        assert_eq!(None, WeightedValues::default().median());
+    }
+    #[test]
+    fn test_quantile() {
+        let tar = vec![5., 1., 4., 2., 3.];
+        assert_eq!(1., Quantile::new(0.1).pred(&tar), "Wrong low quantile");
+        assert_eq!(3., Quantile::new(0.5).pred(&tar), "Wrong median");
+        assert_eq!(5., Quantile::new(0.9).pred(&tar), "Wrong high quantile");
+        let weighted = WeightedTarget::new(vec![1., 2., 3.], vec![1., 1., 8.]);
+        assert_eq!(
+            3.,
+            Quantile::new(0.25).pred(&weighted),
+            "Wrong weighted quantile"
+        );
+        // Leaf 2: 0.25 * (3 + 2 + 1) + 0.75 * 1
+        let loss = Quantile::new(0.25).node_score(&tar);
+        assert!((loss - 2.25).abs() < 1e-12, "Wrong pinball loss");
+    }
+    #[test]
+    fn test_quantile_interval() {
+        // Deterministic pseudo random noise around a step function
+        let mut rng = Lcg::new(11);
+        let values: Vec<f64> = (0..400).map(|_| rng.uniform()).collect();
+        let tar: Vec<f64> = values
+            .as_slice()
+            .iter()
+            .map(|v| if *v < 0.5 { 0. } else { 10. } + 4. * rng.uniform())
+            .collect();
+        let data = HashMap::from([("f1".to_owned(), values)]);
+        let tree_config = TreeConfig {
+            max_depth: 2,
+            min_samples_leaf: 40,
+            ..Default::default()
+        };
+        let low = Tree::fit(&data, &tar, &tree_config, &Quantile::new(0.05)).unwrap();
+        let high = Tree::fit(&data, &tar, &tree_config, &Quantile::new(0.95)).unwrap();
+        let recomputed_config = TreeConfig {
+            recompute_histograms: true,
+            ..tree_config
+        };
+        assert_eq!(
+            high,
+            Tree::fit(&data, &tar, &recomputed_config, &Quantile::new(0.95)).unwrap(),
+            "Subtracted histograms should match the recomputed ones"
+        );
+        let (low, high) = (low.predict(&data).unwrap(), high.predict(&data).unwrap());
+        let covered = tar
+            .as_slice()
+            .iter()
+            .zip(low.iter().zip(high.iter()))
+            .filter(|(value, (low, high))| *low <= **value && **value <= *high)
+            .count();
+        let coverage = covered as f64 / tar.len() as f64;
+        assert!(
+            (0.85..=0.95).contains(&coverage),
+            "Wrong coverage {}",
+            coverage
+        );
+    }
+    #[test]
+    fn test_quantile_boosting() {
+        let data = HashMap::from([("f1".to_owned(), (0..8).map(f64::from).collect::<Vec<_>>())]);
+        let tar = vec![1., 2., 4., 3., 10., 12., 11., 20.];
+        let config = GradientBoostingConfig {
+            n_estimators: 1,
+            learning_rate: 1.,
+            tree_config: TreeConfig {
+                max_depth: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let quantile = Quantile::new(0.75);
+        let model = GradientBoosting::fit(&data, &tar, &config, &quantile).unwrap();
+        assert_eq!(11., model.base_score, "Wrong base score");
+        // Leaves hold the quantile of the residuals of their rows, not a Newton step
+        let leaves = model.trees[0].predict_leaf_index(&data).unwrap();
+        let preds = model.predict(&data).unwrap();
+        for (leaf, pred) in leaves.iter().zip(preds) {
+            let leaf_target: Vec<f64> = (0..tar.len())
+                .filter(|row| leaves[*row] == *leaf)
+                .map(|row| tar[row])
+                .collect();
+            assert_eq!(quantile.pred(&leaf_target), pred, "Wrong leaf value");
+        }
+        let config = GradientBoostingConfig {
+            n_estimators: 20,
+            learning_rate: 0.5,
+            ..config
+        };
+        let model = GradientBoosting::fit(&data, &tar, &config, &quantile).unwrap();
+        let covered = tar
+            .as_slice()
+            .iter()
+            .zip(model.predict(&data).unwrap())
+            .filter(|(value, pred)| **value <= *pred)
+            .count();
+        assert_eq!(6, covered, "Wrong coverage");
+    }
+}
@@ -0,0 +1,391 @@
+use super::quantile::{pinball_loss, Moments, Quantile, WeightedValues};
+use super::split_stats::{SplitStats, ValueStats};
+use super::split_values::{NullDirection, SplitScore};
+use super::{Score, ScoreError};
+use crate::tree::split::{check_target_values, Target};
+
+/// Scores the split sending null rows to the side with the lowest total loss,
+/// `loss` being the loss of a child from the stats of its parts.
+pub(super) fn min_loss_split(
+    left: &ValueStats,
+    right: &ValueStats,
+    null: &ValueStats,
+    loss: impl Fn(&[&ValueStats]) -> f64,
+) -> Result<SplitScore, ScoreError> {
+    let total_len = left.count() + right.count() + null.count();
+    if total_len == left.count() || total_len == right.count() || total_len == null.count() {
+        return Err(ScoreError::PerfectSplit);
+    }
+    let score_on_left = loss(&[left, null]) + loss(&[right]);
+    let score_on_right = loss(&[left]) + loss(&[right, null]);
+    if score_on_left <= score_on_right {
+        Ok(SplitScore {
+            score: score_on_left,
+            null_direction: NullDirection::Left,
+        })
+    } else if score_on_right.is_finite() {
+        Ok(SplitScore {
+            score: score_on_right,
+            null_direction: NullDirection::Right,
+        })
+    } else {
+        Err(ScoreError::NanReturn)
+    }
+}
+
+/// Checks that every value of `target` is finite, NaN or infinite values would make
+/// every split score NaN.
+pub(super) fn validate_finite(target: &impl Target<f64>) -> Result<(), ScoreError> {
+    check_target_values(target.iter(), f64::is_finite, "finite")
+}
+
+/// Least squares regression: splits minimize the sum of squared errors, leaves hold the mean.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SquaredError;
+
+impl Score<f64> for SquaredError {
+    type Stats = ValueStats;
+    fn empty_stats(&self) -> ValueStats {
+        ValueStats::moments()
+    }
+    fn update_stats(&self, stats: &mut ValueStats, value: f64, weight: f64) {
+        stats.add(value, weight);
+    }
+    fn validate_target(&self, target: &impl Target<f64>) -> Result<(), ScoreError> {
+        validate_finite(target)
+    }
+    fn stats_score(
+        &self,
+        left: &ValueStats,
+        right: &ValueStats,
+        null: &ValueStats,
+    ) -> Result<SplitScore, ScoreError> {
+        min_loss_split(left, right, null, |parts| {
+            ValueStats::merged_moments(parts).sum_squared_error()
+        })
+    }
+    fn pred(&self, target: &impl Target<f64>) -> f64 {
+        self.target_stats(target).mean()
+    }
+    fn node_score(&self, target: &impl Target<f64>) -> f64 {
+        self.target_stats(target).sum_squared_error()
+    }
+}
+
+/// Least absolute deviation regression: splits minimize the sum of absolute errors,
+/// leaves hold the median.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AbsoluteError;
+
+impl AbsoluteError {
+    fn loss(parts: &[&ValueStats]) -> f64 {
+        let values = ValueStats::merged_values(parts);
+        values
+            .median()
+            .map_or(0., |median| 2. * pinball_loss(&values, median, 0.5))
+    }
+}
+
+impl Score<f64> for AbsoluteError {
+    type Stats = ValueStats;
+    fn empty_stats(&self) -> ValueStats {
+        ValueStats::with_values()
+    }
+    fn update_stats(&self, stats: &mut ValueStats, value: f64, weight: f64) {
+        stats.add(value, weight);
+    }
+    fn validate_target(&self, target: &impl Target<f64>) -> Result<(), ScoreError> {
+        validate_finite(target)
+    }
+    fn stats_score(
+        &self,
+        left: &ValueStats,
+        right: &ValueStats,
+        null: &ValueStats,
+    ) -> Result<SplitScore, ScoreError> {
+        min_loss_split(left, right, null, AbsoluteError::loss)
+    }
+    fn pred(&self, target: &impl Target<f64>) -> f64 {
+        ValueStats::merged_values(&[&self.target_stats(target)])
+            .median()
+            .unwrap_or(f64::NAN)
+    }
+    fn node_score(&self, target: &impl Target<f64>) -> f64 {
+        AbsoluteError::loss(&[&self.target_stats(target)])
+    }
+}
+
+/// Huber regression: quadratic loss for errors smaller than `delta`, linear above.
+/// Leaves hold the median shifted by the mean of the residuals clipped to `delta`.
+#[derive(Debug, Clone, Copy)]
+pub struct Huber {
+    pub delta: f64,
+}
+
+impl Huber {
+    pub fn new(delta: f64) -> Self {
+        match delta > 0. {
+            true => Huber { delta },
+            false => panic!("Huber delta must be gt than 0"),
+        }
+    }
+    /// Median shifted by the mean of the residuals clipped to `delta`: residuals below
+    /// `-delta` and from `delta` on are clipped, the other ones are summed as they are.
+    fn leaf_value(&self, values: &WeightedValues) -> Option<f64> {
+        let median = values.median()?;
+        let total = values.total();
+        let low = values.below(median - self.delta);
+        let high = total.minus(&values.below(median + self.delta));
+        let middle = total.minus(&low).minus(&high);
+        let shift = self.delta * (high.weight - low.weight) + middle.sum - median * middle.weight;
+        Some(median + shift / total.weight)
+    }
+    fn loss(&self, parts: &[&ValueStats]) -> f64 {
+        let values = ValueStats::merged_values(parts);
+        let Some(leaf) = self.leaf_value(&values) else {
+            return 0.;
+        };
+        // Linear loss below leaf - delta and from leaf + delta on, quadratic in between
+        let total = values.total();
+        let low = values.below(leaf - self.delta);
+        let high = total.minus(&values.below(leaf + self.delta));
+        let middle = total.minus(&low).minus(&high);
+        let linear = |moments: &Moments, sign: f64| {
+            self.delta * sign * (moments.sum - leaf * moments.weight)
+                - self.delta * self.delta / 2. * moments.weight
+        };
+        let quadratic =
+            (middle.sum_sq - 2. * leaf * middle.sum + leaf * leaf * middle.weight).max(0.) / 2.;
+        linear(&low, -1.) + quadratic + linear(&high, 1.)
+    }
+}
+
+impl Score<f64> for Huber {
+    type Stats = ValueStats;
+    fn empty_stats(&self) -> ValueStats {
+        ValueStats::with_values()
+    }
+    fn update_stats(&self, stats: &mut ValueStats, value: f64, weight: f64) {
+        stats.add(value, weight);
+    }
+    fn validate_target(&self, target: &impl Target<f64>) -> Result<(), ScoreError> {
+        validate_finite(target)
+    }
+    fn stats_score(
+        &self,
+        left: &ValueStats,
+        right: &ValueStats,
+        null: &ValueStats,
+    ) -> Result<SplitScore, ScoreError> {
+        min_loss_split(left, right, null, |parts| self.loss(parts))
+    }
+    fn pred(&self, target: &impl Target<f64>) -> f64 {
+        self.leaf_value(&ValueStats::merged_values(&[&self.target_stats(target)]))
+            .unwrap_or(f64::NAN)
+    }
+    fn node_score(&self, target: &impl Target<f64>) -> f64 {
+        self.loss(&[&self.target_stats(target)])
+    }
+}
+
+/// Regression score functions selectable at runtime. Kept apart from `ScoringFunction`:
+/// an enum implements `Score` for a single target type with a single stats type, and
+/// the classification scores fit `bool` targets on class counts while these fit `f64`
+/// targets on value stats.
+pub enum RegressionScore {
+    SquaredError(SquaredError),
+    AbsoluteError(AbsoluteError),
+    Huber(Huber),
+    Quantile(Quantile),
+}
+
+impl std::fmt::Display for RegressionScore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let displayable = match self {
+            RegressionScore::SquaredError(_) => "SquaredError",
+            RegressionScore::AbsoluteError(_) => "AbsoluteError",
+            RegressionScore::Huber(_) => "Huber",
+            RegressionScore::Quantile(_) => "Quantile",
+        };
+        write!(f, "{}", displayable)
+    }
+}
+
+impl Score<f64> for RegressionScore {
+    type Stats = ValueStats;
+    fn empty_stats(&self) -> ValueStats {
+        match self {
+            RegressionScore::SquaredError(s) => s.empty_stats(),
+            RegressionScore::AbsoluteError(a) => a.empty_stats(),
+            RegressionScore::Huber(h) => h.empty_stats(),
+            RegressionScore::Quantile(q) => q.empty_stats(),
+        }
+    }
+    fn update_stats(&self, stats: &mut ValueStats, value: f64, weight: f64) {
+        match self {
+            RegressionScore::SquaredError(s) => s.update_stats(stats, value, weight),
+            RegressionScore::AbsoluteError(a) => a.update_stats(stats, value, weight),
+            RegressionScore::Huber(h) => h.update_stats(stats, value, weight),
+            RegressionScore::Quantile(q) => q.update_stats(stats, value, weight),
+        }
+    }
+    fn validate_target(&self, target: &impl Target<f64>) -> Result<(), ScoreError> {
+        match self {
+            RegressionScore::SquaredError(s) => s.validate_target(target),
+            RegressionScore::AbsoluteError(a) => a.validate_target(target),
+            RegressionScore::Huber(h) => h.validate_target(target),
+            RegressionScore::Quantile(q) => q.validate_target(target),
+        }
+    }
+    fn stats_score(
+        &self,
+        left: &ValueStats,
+        right: &ValueStats,
+        null: &ValueStats,
+    ) -> Result<SplitScore, ScoreError> {
+        match self {
+            RegressionScore::SquaredError(s) => s.stats_score(left, right, null),
+            RegressionScore::AbsoluteError(a) => a.stats_score(left, right, null),
+            RegressionScore::Huber(h) => h.stats_score(left, right, null),
+            RegressionScore::Quantile(q) => q.stats_score(left, right, null),
+        }
+    }
+    fn stats_hessian(&self, stats: &ValueStats) -> f64 {
+        match self {
+            RegressionScore::SquaredError(s) => s.stats_hessian(stats),
+            RegressionScore::AbsoluteError(a) => a.stats_hessian(stats),
+            RegressionScore::Huber(h) => h.stats_hessian(stats),
+            RegressionScore::Quantile(q) => q.stats_hessian(stats),
+        }
+    }
+    fn pred(&self, target: &impl Target<f64>) -> f64 {
+        match self {
+            RegressionScore::SquaredError(s) => s.pred(target),
+            RegressionScore::AbsoluteError(a) => a.pred(target),
+            RegressionScore::Huber(h) => h.pred(target),
+            RegressionScore::Quantile(q) => q.pred(target),
+        }
+    }
+    fn node_score(&self, target: &impl Target<f64>) -> f64 {
+        match self {
+            RegressionScore::SquaredError(s) => s.node_score(target),
+            RegressionScore::AbsoluteError(a) => a.node_score(target),
+            RegressionScore::Huber(h) => h.node_score(target),
+            RegressionScore::Quantile(q) => q.node_score(target),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tree::split::{DataSet, WeightedTarget};
+    use crate::tree::{Tree, TreeConfig, TreeError};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_squared_error_split() {
+        let data = HashMap::from([("f1".to_owned(), vec![1., 2., 3., 4., f64::NAN])]);
+        let tar = vec![1., 2., 10., 12., 11.];
+        let tree_config = TreeConfig::default();
+        let (split, mask) = data
+            .find_best_split(&tar, &SquaredError, &tree_config)
+            .unwrap();
+        // {1, 2} vs {10, 12, 11}: 0.5 + 2
+        assert_eq!(3., split.value, "Wrong split");
+        assert_eq!(2.5, split.score.score, "Wrong score");
+        assert_eq!(NullDirection::Right, split.score.null_direction);
+        let mask: Vec<_> = mask.collect();
+        assert_eq!(
+            vec![Some(true), Some(true), Some(false), Some(false), None],
+            mask
+        );
+    }
+    #[test]
+    fn test_absolute_error_split() {
+        let data = HashMap::from([("f1".to_owned(), vec![1., 2., 3., 4., 5.])]);
+        // the outlier drags the squared error split away from the bulk of the values
+        let tar = vec![3., 3., 20., 20., 50.];
+        let tree_config = TreeConfig::default();
+        let (l1, _) = data
+            .find_best_split(&tar, &AbsoluteError, &tree_config)
+            .unwrap();
+        let (l2, _) = data
+            .find_best_split(&tar, &SquaredError, &tree_config)
+            .unwrap();
+        // {3, 3} vs {20, 20, 50}: 0 + 30
+        assert_eq!(3., l1.value, "Wrong absolute error split");
+        assert_eq!(30., l1.score.score, "Wrong absolute error score");
+        assert_eq!(5., l2.value, "Wrong squared error split");
+        assert_eq!(20., AbsoluteError.pred(&tar), "Leaf should be the median");
+        assert_eq!(1.5, AbsoluteError.pred(&vec![1., 2.]), "Wrong even median");
+        let weighted = WeightedTarget::new(vec![1., 2., 3.], vec![1., 1., 3.]);
+        assert_eq!(3., AbsoluteError.pred(&weighted), "Wrong weighted median");
+    }
+    #[test]
+    fn test_huber() {
+        let huber = Huber::new(1.);
+        let tar = vec![0., 1., 2., 3., 100.];
+        // median 2, clipped residuals -1, -1, 0, 1, 1
+        assert_eq!(2., huber.pred(&tar), "Wrong Huber leaf");
+        // (2 - 0.5) + 0.5 + 0 + 0.5 + (98 - 0.5)
+        assert_eq!(100., huber.node_score(&tar), "Wrong Huber loss");
+        let small_residuals = vec![1., 1.5, 2.5];
+        assert_eq!(
+            SquaredError.pred(&small_residuals),
+            huber.pred(&small_residuals),
+            "Huber leaf should be the mean for small residuals"
+        );
+    }
+    #[test]
+    fn test_regression_tree() {
+        let data = HashMap::from([("f1".to_owned(), (0..8).map(f64::from).collect::<Vec<_>>())]);
+        let tar = vec![1., 1., 1., 1., 5., 5., 5., 5.];
+        let tree_config = TreeConfig {
+            max_depth: 3,
+            ..Default::default()
+        };
+        for score_fn in [
+            RegressionScore::SquaredError(SquaredError),
+            RegressionScore::AbsoluteError(AbsoluteError),
+            RegressionScore::Huber(Huber::new(1.)),
+            RegressionScore::Quantile(Quantile::new(0.9)),
+        ] {
+            let tree = Tree::fit(&data, &tar, &tree_config, &score_fn).unwrap();
+            assert_eq!(1, tree.depth(), "Pure leaves split for {}", score_fn);
+            assert_eq!(
+                tar,
+                tree.predict(&data).unwrap(),
+                "Wrong predictions for {}",
+                score_fn
+            );
+        }
+    }
+    #[test]
+    fn test_non_finite_target() {
+        let data = HashMap::from([("f1".to_owned(), vec![1., 2., 3.])]);
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let tar = vec![1., value, 3.];
+            for score_fn in [
+                RegressionScore::SquaredError(SquaredError),
+                RegressionScore::AbsoluteError(AbsoluteError),
+                RegressionScore::Huber(Huber::new(1.)),
+                RegressionScore::Quantile(Quantile::new(0.5)),
+            ] {
+                let result = Tree::fit(&data, &tar, &TreeConfig::default(), &score_fn);
+                assert!(
+                    matches!(
+                        result,
+                        Err(TreeError::ScoreError(ScoreError::InvalidTargetValue {
+                            expected: "finite",
+                            ..
+                        }))
+                    ),
+                    "{} target accepted by {}",
+                    value,
+                    score_fn
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use super::gradients::GradHess;
+use super::quantile::WeightedValues;
+
+/// Sufficient statistics of a set of target values: a score computes split scores
+/// from these alone, so they can be accumulated per histogram bin and combined.
+pub trait SplitStats: Clone + Send + Sync {
+    fn merge(&mut self, other: &Self);
+    fn subtract(&mut self, other: &Self);
+    /// Number of rows accumulated.
+    fn count(&self) -> usize;
+    /// Sum of the weights of the rows accumulated.
+    fn weight(&self) -> f64 {
+        self.count() as f64
+    }
+    /// Value the categories of a categorical feature are sorted by before scanning their
+    /// partitions (Fisher's method), e.g. the mean target or the gradient ratio.
+    fn category_key(&self) -> f64;
+}
+
+/// Per-class label counts, weighted by the row weights.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassCounts {
+    counts: Vec<f64>,
+    n: usize,
+}
+
+impl ClassCounts {
+    pub fn new(n_classes: usize) -> Self {
+        ClassCounts {
+            counts: vec![0.; n_classes],
+            n: 0,
+        }
+    }
+    pub fn add(&mut self, class: usize, weight: f64) {
+        self.counts[class] += weight;
+        self.n += 1;
+    }
+    pub fn counts(&self) -> &[f64] {
+        &self.counts
+    }
+    pub fn total(&self) -> f64 {
+        self.counts.iter().sum()
+    }
+}
+
+impl SplitStats for ClassCounts {
+    fn merge(&mut self, other: &Self) {
+        for (c, o) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *c += o;
+        }
+        self.n += other.n;
+    }
+    fn subtract(&mut self, other: &Self) {
+        for (c, o) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *c -= o;
+        }
+        self.n -= other.n;
+    }
+    fn count(&self) -> usize {
+        self.n
+    }
+    fn weight(&self) -> f64 {
+        self.total()
+    }
+    /// Proportion of the last class, the positive one for binary targets.
+    fn category_key(&self) -> f64 {
+        match self.total() {
+            0. => 0.,
+            total => self.counts.last().copied().unwrap_or(0.) / total,
+        }
+    }
+}
+
+/// Sums of the gradients and hessians of a set of rows, weighted by the row weights.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GradHessSums {
+    pub grad: f64,
+    pub hess: f64,
+    n: usize,
+}
+
+impl GradHessSums {
+    pub fn add(&mut self, value: GradHess, weight: f64) {
+        self.grad += weight * value.grad;
+        self.hess += weight * value.hess;
+        self.n += 1;
+    }
+}
+
+impl SplitStats for GradHessSums {
+    fn merge(&mut self, other: &Self) {
+        self.grad += other.grad;
+        self.hess += other.hess;
+        self.n += other.n;
+    }
+    fn subtract(&mut self, other: &Self) {
+        self.grad -= other.grad;
+        self.hess -= other.hess;
+        self.n -= other.n;
+    }
+    fn count(&self) -> usize {
+        self.n
+    }
+    /// Gradient ratio `G / H`, the opposite of the Newton step of the rows.
+    fn category_key(&self) -> f64 {
+        match self.hess {
+            0. => 0.,
+            hess => self.grad / hess,
+        }
+    }
+}
+
+/// Weighted moments of a set of continuous target values. Scores that need the value
+/// distribution (e.g. medians) also keep the values.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValueStats {
+    pub sum: f64,
+    pub sum_sq: f64,
+    pub weight: f64,
+    n: usize,
+    values: Option<WeightedValues>,
+}
+
+impl ValueStats {
+    /// Stats keeping only the moments of the values.
+    pub fn moments() -> Self {
+        ValueStats::default()
+    }
+    /// Stats keeping the values along with their moments.
+    pub fn with_values() -> Self {
+        ValueStats {
+            values: Some(WeightedValues::default()),
+            ..Default::default()
+        }
+    }
+    pub fn add(&mut self, value: f64, weight: f64) {
+        self.sum += weight * value;
+        self.sum_sq += weight * value * value;
+        self.weight += weight;
+        self.n += 1;
+        if let Some(values) = self.values.as_mut() {
+            values.add(value, weight);
+        }
+    }
+    pub fn mean(&self) -> f64 {
+        self.sum / self.weight
+    }
+    /// Weighted sum of the squared deviations from the mean.
+    pub fn sum_squared_error(&self) -> f64 {
+        match self.weight {
+            0. => 0.,
+            weight => (self.sum_sq - self.sum * self.sum / weight).max(0.),
+        }
+    }
+    /// Moments of the union of `parts`, without their values.
+    pub fn merged_moments(parts: &[&ValueStats]) -> ValueStats {
+        parts
+            .iter()
+            .fold(ValueStats::moments(), |mut merged, part| {
+                merged.sum += part.sum;
+                merged.sum_sq += part.sum_sq;
+                merged.weight += part.weight;
+                merged.n += part.n;
+                merged
+            })
+    }
+    /// Values of the union of `parts`, stats keeping only moments have none.
+    pub fn merged_values(parts: &[&ValueStats]) -> WeightedValues {
+        parts.iter().filter_map(|part| part.values.as_ref()).fold(
+            WeightedValues::default(),
+            |mut merged, values| {
+                merged.merge(values);
+                merged
+            },
+        )
+    }
+}
+
+impl SplitStats for ValueStats {
+    fn merge(&mut self, other: &Self) {
+        self.sum += other.sum;
+        self.sum_sq += other.sum_sq;
+        self.weight += other.weight;
+        self.n += other.n;
+        if let (Some(values), Some(other)) = (self.values.as_mut(), other.values.as_ref()) {
+            values.merge(other);
+        }
+    }
+    fn subtract(&mut self, other: &Self) {
+        self.sum -= other.sum;
+        self.sum_sq -= other.sum_sq;
+        self.weight -= other.weight;
+        self.n -= other.n;
+        if let (Some(values), Some(other)) = (self.values.as_mut(), other.values.as_ref()) {
+            values.subtract(other);
+        }
+    }
+    fn count(&self) -> usize {
+        self.n
+    }
+    fn weight(&self) -> f64 {
+        self.weight
+    }
+    fn category_key(&self) -> f64 {
+        match self.weight {
+            0. => 0.,
+            _ => self.mean(),
+        }
+    }
+}
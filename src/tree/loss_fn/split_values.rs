@@ -1,13 +1,14 @@
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum NullDirection {
     #[default]
     Left,
     Right,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct SplitScore {
     pub score: f64,
     pub null_direction: NullDirection,
@@ -28,11 +29,49 @@ impl std::fmt::Display for SplitScore {
         )
     }
 }
-#[derive(Debug, Clone, PartialEq)]
+/// Partition of the categories of a categorical feature. Categories that were not
+/// seen in the node while fitting go to `unseen_direction`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CategorySplit {
+    /// Sorted codes of the categories going left.
+    pub left: Vec<u32>,
+    /// Sorted codes of the categories going right.
+    pub right: Vec<u32>,
+    pub unseen_direction: NullDirection,
+}
+
+impl CategorySplit {
+    /// Whether a category goes left, unseen categories follow `unseen_direction`.
+    pub fn goes_left(&self, category: u32) -> bool {
+        if self.left.binary_search(&category).is_ok() {
+            true
+        } else if self.right.binary_search(&category).is_ok() {
+            false
+        } else {
+            self.unseen_direction == NullDirection::Left
+        }
+    }
+}
+
+/// How a split routes the present values of its feature.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum SplitKind {
+    /// Values lower than `SplitInfo::value` go left.
+    #[default]
+    Threshold,
+    /// The category whose code is `SplitInfo::value` goes left, every other one goes right.
+    Equality,
+    /// Categories go to the side of the partition they belong to.
+    Categories(CategorySplit),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SplitInfo {
     pub name: String,
+    /// Threshold of numerical splits or category of equality splits, 0 for partitions.
     pub value: f64,
     pub score: SplitScore,
+    pub kind: SplitKind,
 }
 
 impl std::fmt::Display for NullDirection {
@@ -47,7 +86,37 @@ impl std::fmt::Display for NullDirection {
 
 impl SplitInfo {
     pub fn new(name: String, value: f64, score: SplitScore) -> SplitInfo {
-        SplitInfo { name, value, score }
+        SplitInfo {
+            name,
+            value,
+            score,
+            kind: SplitKind::Threshold,
+        }
+    }
+    pub fn categorical(name: String, categories: CategorySplit, score: SplitScore) -> SplitInfo {
+        SplitInfo {
+            name,
+            value: 0.,
+            score,
+            kind: SplitKind::Categories(categories),
+        }
+    }
+    /// One category vs rest split, unseen categories go right with the rest.
+    pub fn equality(name: String, category: u32, score: SplitScore) -> SplitInfo {
+        SplitInfo {
+            name,
+            value: category as f64,
+            score,
+            kind: SplitKind::Equality,
+        }
+    }
+    /// Whether a present value goes left.
+    pub fn goes_left(&self, value: f64) -> bool {
+        match &self.kind {
+            SplitKind::Threshold => value < self.value,
+            SplitKind::Equality => value == self.value,
+            SplitKind::Categories(categories) => categories.goes_left(value as u32),
+        }
     }
 }
 
@@ -59,10 +128,22 @@ impl PartialOrd for SplitInfo {
 
 impl std::fmt::Display for SplitInfo {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Feature Name: {}\nThreshold: {}\nScore: {}",
-            self.name, self.value, self.score
-        )
+        match &self.kind {
+            SplitKind::Threshold => write!(
+                f,
+                "Feature Name: {}\nThreshold: {}\nScore: {}",
+                self.name, self.value, self.score
+            ),
+            SplitKind::Equality => write!(
+                f,
+                "Feature Name: {}\nCategory: {}\nScore: {}",
+                self.name, self.value, self.score
+            ),
+            SplitKind::Categories(categories) => write!(
+                f,
+                "Feature Name: {}\nLeft Categories: {:?}\nScore: {}",
+                self.name, categories.left, self.score
+            ),
+        }
     }
 }
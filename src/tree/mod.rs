@@ -1,12 +1,90 @@
-use loss_fn::{split_values::SplitInfo, Score};
-use split::{DataSet, Target};
+use crate::metrics::{check_early_stopping_rounds, EvalHistory, EvalRecord, Metric};
+use growth::GrowthPolicy;
+use loss_fn::split_values::{NullDirection, SplitInfo};
+use loss_fn::Score;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use split::histogram::{best_split_among, compact_histograms, subtract_histograms, Histograms};
+use split::{partition_rows, DataSet, SplitStrategy, Target, TargetRows};
+use std::cell::RefCell;
+use std::marker::PhantomData;
 
+pub mod compiled;
+pub mod growth;
 pub mod loss_fn;
+pub mod persistence;
+pub mod shap;
 pub mod split;
 
-#[derive(Debug, Default)]
+/// Number of features considered when searching the split of a node.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum MaxFeatures {
+    #[default]
+    All,
+    /// Square root of the number of features.
+    Sqrt,
+    /// Base 2 logarithm of the number of features.
+    Log2,
+    /// Fraction of the features, in `(0, 1]`.
+    Fraction(f64),
+    /// Fixed number of features, all of them if there are fewer.
+    Count(usize),
+}
+
+impl MaxFeatures {
+    /// Number of features sampled out of `n_features`, at least one.
+    /// Panics if a `Fraction` is not in `(0, 1]`.
+    pub fn n_features(&self, n_features: usize) -> usize {
+        let n = match self {
+            MaxFeatures::All => n_features,
+            MaxFeatures::Sqrt => (n_features as f64).sqrt() as usize,
+            MaxFeatures::Log2 => (n_features as f64).log2() as usize,
+            MaxFeatures::Fraction(fraction) if !(*fraction > 0. && *fraction <= 1.) => {
+                panic!("Max features fraction must be gt than 0 and lte than 1")
+            }
+            MaxFeatures::Fraction(fraction) => (fraction * n_features as f64) as usize,
+            MaxFeatures::Count(count) => *count,
+        };
+        n.clamp(1, n_features.max(1))
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct TreeConfig {
+    /// Maximum depth of the tree, a depth of 0 yields a single leaf.
     pub max_depth: usize,
+    /// Minimum number of samples a node needs to be considered for splitting.
+    pub min_samples_split: usize,
+    /// Minimum number of samples in each child of a split.
+    pub min_samples_leaf: usize,
+    /// Minimum gain (`node_score - split_score`) required to split a node.
+    pub min_impurity_decrease: f64,
+    /// Maximum number of leaves of the tree, unlimited if `None`.
+    pub max_leaf_nodes: Option<usize>,
+    /// Minimum sum of hessians in each child of a split.
+    pub min_child_weight: f64,
+    /// Order in which nodes are expanded.
+    pub growth_policy: GrowthPolicy,
+    /// Categorical features with at most this many categories are split one category
+    /// vs rest instead of on partitions of their categories.
+    pub max_cat_to_onehot: usize,
+    /// Minimum number of rows of a category for it to form a group on its own,
+    /// and minimum number of rows in each group of a categorical split.
+    pub min_data_per_group: usize,
+    /// Features sampled for the split search of every node.
+    pub max_features: MaxFeatures,
+    /// How the thresholds of numerical features are searched.
+    pub split_strategy: SplitStrategy,
+    /// Seed of the feature sampling and of the random thresholds.
+    pub seed: u64,
+    /// Build the histograms of both children from scratch instead of
+    /// deriving the larger one from its parent and sibling.
+    pub recompute_histograms: bool,
+    /// Number of levels without improving the validation metric after which
+    /// `Tree::fit_with_eval` stops growing the tree, every level is grown if `None`.
+    pub early_stopping_rounds: Option<usize>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -17,84 +95,152 @@ pub enum TreeError {
     CouldNotFindFeature(String),
     #[error("Found leaf with no prediction")]
     NoPredictionInLeaf,
+    #[error("Found leaf with {0} values, expected a single one")]
+    MultiValueLeaf(usize),
+    #[error("Leaves do not hold class probabilities")]
+    NoProbabilityLeaves,
+    #[error("Tree Error: {0}")]
+    ScoreError(#[from] loss_fn::ScoreError),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Tree {
     pub split_info: Option<SplitInfo>,
     pub left: Option<Box<Tree>>,
     pub right: Option<Box<Tree>>,
-    pub prediction: Option<f64>, // Optional: only used at leaf nodes
+    pub prediction: Option<Vec<f64>>, // Optional: only used at leaf nodes
+    /// Sum of the weights of the training rows that reached the node.
+    pub cover: f64,
 }
 
-impl Tree {
-    pub fn fit<T, S: Score<T>>(
-        samples: &impl DataSet,
-        target: &impl Target<T>,
-        tree_config: &TreeConfig,
-        score_fn: &S,
-    ) -> Result<Tree, TreeError> {
-        let max_depth = tree_config.max_depth.clone();
-        Tree::build_tree_recursive(samples, target, max_depth, score_fn, None)
+/// A split traversed by a sample, see `Tree::decision_path`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecisionStep<'a> {
+    pub split_info: &'a SplitInfo,
+    pub went_left: bool,
+    /// Whether the value of the sample was missing and followed the null direction.
+    pub null_direction_taken: bool,
+}
+
+/// Best split found for a node, with the mask that routes its rows
+/// and the histograms it was found on.
+struct NodeSplit<St> {
+    split_info: SplitInfo,
+    mask: Vec<Option<bool>>,
+    gain: f64,
+    histograms: Histograms<St>,
+}
+
+type ChildrenHistograms<St> = (Option<Histograms<St>>, Option<Histograms<St>>);
+/// Rows of a leaf of the last level grown, and its histograms if derived from its parent.
+type LevelLeaf<St> = (Vec<usize>, Option<Histograms<St>>);
+
+/// Everything shared by the nodes of a tree while it is fitted: nodes only own
+/// a range of row indices, which is partitioned in place when the node is split.
+struct TreeBuilder<'a, T, D, Tg, S> {
+    samples: &'a D,
+    target: &'a Tg,
+    tree_config: &'a TreeConfig,
+    split_function: &'a S,
+    rng: RefCell<StdRng>,
+    target_type: PhantomData<T>,
+}
+
+impl<'a, T, D: DataSet, Tg: Target<T>, S: Score<T>> TreeBuilder<'a, T, D, Tg, S> {
+    fn new(
+        samples: &'a D,
+        target: &'a Tg,
+        tree_config: &'a TreeConfig,
+        split_function: &'a S,
+    ) -> Self {
+        TreeBuilder {
+            samples,
+            target,
+            tree_config,
+            split_function,
+            rng: RefCell::new(StdRng::seed_from_u64(tree_config.seed)),
+            target_type: PhantomData,
+        }
     }
-    fn build_leaf<T, S: Score<T>>(target: &impl Target<T>, split_function: &S) -> Tree {
-        let pred = split_function.pred(target);
+    fn build_leaf(&self, rows: &[usize]) -> Tree {
+        let pred = self
+            .split_function
+            .leaf_value(&TargetRows::new(self.target, rows));
         Tree {
             split_info: None,
             left: None,
             right: None,
             prediction: Some(pred),
+            cover: self.cover(rows),
         }
     }
-    fn build_tree_recursive<T, S: Score<T>>(
-        samples: &impl DataSet,
-        target: &impl Target<T>,
-        max_depth: usize,
-        split_function: &S,
-        split_info_parent: Option<&SplitInfo>,
-    ) -> Result<Tree, TreeError> {
-        if max_depth == 0 {
-            return Ok(Tree::build_leaf(target, split_function));
-        }
-        match samples.find_best_split(target, split_function) {
-            Ok((split_info, mask)) => {
-                //Not really sure why logit does not fit correctly with this one
-                if let Some(_) = split_info_parent {
-                    if split_info.score.score == 0. {
-                        return Ok(Tree::build_leaf(target, split_function));
-                    }
-                }
-                let (left_samples, right_samples) =
-                    samples.split(mask.clone(), split_info.score.null_direction);
-                let (left_tar, right_tar) = target.split(mask, split_info.score.null_direction);
-                let left_tree = Self::build_tree_recursive(
-                    &left_samples,
-                    &left_tar,
-                    max_depth,
-                    split_function,
-                    Some(&split_info),
-                )?;
-                let right_tree = Self::build_tree_recursive(
-                    &right_samples,
-                    &right_tar,
-                    max_depth,
-                    split_function,
-                    Some(&split_info),
-                )?;
-                Ok(Tree {
-                    split_info: Some(split_info),
-                    left: Some(Box::new(left_tree)),
-                    right: Some(Box::new(right_tree)),
-                    prediction: None,
+    fn cover(&self, rows: &[usize]) -> f64 {
+        rows.iter().map(|row| self.target.weight(*row)).sum()
+    }
+    /// Features the split of a node is searched on, sampled when `max_features` is set.
+    fn node_features<'h>(&self, histograms: &'h Histograms<S::Stats>) -> Vec<&'h String> {
+        // Sorted, the sample must not depend on the order of the map
+        let mut features: Vec<&String> = histograms.keys().collect();
+        features.sort();
+        let n_features = self.tree_config.max_features.n_features(features.len());
+        if n_features < features.len() {
+            let mut rng = self.rng.borrow_mut();
+            features.partial_shuffle(&mut *rng, n_features);
+            features.truncate(n_features);
+        }
+        features
+    }
+    fn has_leaf_budget(&self, n_leaves: usize) -> bool {
+        self.tree_config
+            .max_leaf_nodes
+            .is_none_or(|max_leaves| n_leaves < max_leaves)
+    }
+    /// Finds the split of a node, `None` means that the node must be a leaf.
+    /// Histograms are built from scratch if not derived from the parent ones.
+    fn node_split(
+        &self,
+        rows: &[usize],
+        histograms: Option<Histograms<S::Stats>>,
+        depth: usize,
+    ) -> Option<NodeSplit<S::Stats>> {
+        let tree_config = self.tree_config;
+        if depth >= tree_config.max_depth || rows.len() < tree_config.min_samples_split.max(2) {
+            return None;
+        }
+        let histograms = histograms.unwrap_or_else(|| {
+            self.samples
+                .histograms(rows, self.target, self.split_function, None)
+        });
+        let features = self.node_features(&histograms);
+        let seed = match tree_config.split_strategy {
+            SplitStrategy::Best => 0,
+            SplitStrategy::Random => self.rng.borrow_mut().gen(),
+        };
+        let split = best_split_among(
+            &histograms,
+            &features,
+            self.split_function,
+            tree_config,
+            seed,
+        );
+        match split {
+            Ok(split_info) => {
+                let node_target = TargetRows::new(self.target, rows);
+                let gain = self.split_function.node_score(&node_target) - split_info.score.score;
+                let worth_splitting = gain > 0. && gain >= tree_config.min_impurity_decrease;
+                worth_splitting.then(|| NodeSplit {
+                    mask: self.samples.split_mask(&split_info, rows),
+                    split_info,
+                    gain,
+                    histograms,
                 })
             }
             Err(error) => match error {
-                split::BestSplitNotFound::NoSplitRequired => {
-                    Ok(Tree::build_leaf(target, split_function))
-                }
+                split::BestSplitNotFound::NoSplitRequired
+                | split::BestSplitNotFound::ConstraintsNotMet => None,
                 split::BestSplitNotFound::Score(score_err) => match score_err {
                     loss_fn::ScoreError::InvalidSplit(_) | loss_fn::ScoreError::PerfectSplit => {
-                        Ok(Tree::build_leaf(target, split_function))
+                        None
                     }
                     _ => panic!("Could not split data: {}", score_err),
                 },
@@ -102,40 +248,371 @@ impl Tree {
             },
         }
     }
-    fn predict_single_value<'a, T: Into<f64> + Copy>(
+    /// Histograms of the children of a split: only the smaller child is built,
+    /// its sibling is the parent minus the child. Both only keep the bins of their rows.
+    fn children_histograms(
+        &self,
+        parent: Histograms<S::Stats>,
+        left_rows: &[usize],
+        right_rows: &[usize],
+        depth: usize,
+    ) -> ChildrenHistograms<S::Stats> {
+        if self.tree_config.recompute_histograms || depth >= self.tree_config.max_depth {
+            return (None, None);
+        }
+        let mut sibling = parent;
+        let left_is_smaller = left_rows.len() <= right_rows.len();
+        let small_rows = if left_is_smaller {
+            left_rows
+        } else {
+            right_rows
+        };
+        let mut small_hist =
+            self.samples
+                .histograms(small_rows, self.target, self.split_function, Some(&sibling));
+        subtract_histograms(&mut sibling, &small_hist);
+        compact_histograms(&mut sibling);
+        compact_histograms(&mut small_hist);
+        match left_is_smaller {
+            true => (Some(small_hist), Some(sibling)),
+            false => (Some(sibling), Some(small_hist)),
+        }
+    }
+    fn build_tree_recursive(
+        &self,
+        rows: &mut [usize],
+        histograms: Option<Histograms<S::Stats>>,
+        depth: usize,
+        n_leaves: &mut usize,
+    ) -> Result<Tree, TreeError> {
+        if !self.has_leaf_budget(*n_leaves) {
+            return Ok(self.build_leaf(rows));
+        }
+        let Some(node_split) = self.node_split(rows, histograms, depth) else {
+            return Ok(self.build_leaf(rows));
+        };
+        *n_leaves += 1;
+        let cover = self.cover(rows);
+        let null_direction = node_split.split_info.score.null_direction;
+        let n_left = partition_rows(rows, &node_split.mask, null_direction);
+        let (left_rows, right_rows) = rows.split_at_mut(n_left);
+        let (left_hist, right_hist) =
+            self.children_histograms(node_split.histograms, left_rows, right_rows, depth + 1);
+        let left_tree = self.build_tree_recursive(left_rows, left_hist, depth + 1, n_leaves)?;
+        let right_tree = self.build_tree_recursive(right_rows, right_hist, depth + 1, n_leaves)?;
+        Ok(Tree {
+            split_info: Some(node_split.split_info),
+            left: Some(Box::new(left_tree)),
+            right: Some(Box::new(right_tree)),
+            prediction: None,
+            cover,
+        })
+    }
+    /// Splits the leaves at `depth` of `tree`, given from left to right in `level`, and
+    /// returns the leaves of the next level. Nothing is split if it is empty.
+    fn grow_level(
+        &self,
+        tree: &mut Tree,
+        level: Vec<LevelLeaf<S::Stats>>,
+        depth: usize,
+        n_leaves: &mut usize,
+    ) -> Vec<LevelLeaf<S::Stats>> {
+        let mut next_level = Vec::new();
+        for (leaf, (mut rows, histograms)) in tree.leaves_at_depth_mut(depth).into_iter().zip(level)
+        {
+            if !self.has_leaf_budget(*n_leaves) {
+                continue;
+            }
+            let Some(node_split) = self.node_split(&rows, histograms, depth) else {
+                continue;
+            };
+            *n_leaves += 1;
+            let null_direction = node_split.split_info.score.null_direction;
+            let n_left = partition_rows(&mut rows, &node_split.mask, null_direction);
+            let right_rows = rows.split_off(n_left);
+            let (left_hist, right_hist) =
+                self.children_histograms(node_split.histograms, &rows, &right_rows, depth + 1);
+            leaf.split_info = Some(node_split.split_info);
+            leaf.left = Some(Box::new(self.build_leaf(&rows)));
+            leaf.right = Some(Box::new(self.build_leaf(&right_rows)));
+            leaf.prediction = None;
+            next_level.push((rows, left_hist));
+            next_level.push((right_rows, right_hist));
+        }
+        next_level
+    }
+    /// Copy of `node` whose nodes at `depth` become leaves, fitted on the rows of `rows`
+    /// that reach them.
+    fn truncated(&self, node: &Tree, rows: &mut [usize], depth: usize) -> Tree {
+        let (Some(split_info), Some(left), Some(right)) = (
+            node.split_info.as_ref(),
+            node.left.as_ref(),
+            node.right.as_ref(),
+        ) else {
+            return node.clone();
+        };
+        if depth == 0 {
+            return self.build_leaf(rows);
+        }
+        let mask = self.samples.split_mask(split_info, rows);
+        let n_left = partition_rows(rows, &mask, split_info.score.null_direction);
+        let (left_rows, right_rows) = rows.split_at_mut(n_left);
+        Tree {
+            split_info: Some(split_info.clone()),
+            left: Some(Box::new(self.truncated(left, left_rows, depth - 1))),
+            right: Some(Box::new(self.truncated(right, right_rows, depth - 1))),
+            prediction: None,
+            cover: node.cover,
+        }
+    }
+}
+
+/// Index of the largest value, the first one on ties.
+pub(crate) fn argmax(values: &[f64]) -> usize {
+    values
+        .iter()
+        .enumerate()
+        .fold((0, f64::NEG_INFINITY), |(best, max), (idx, v)| {
+            if *v > max {
+                (idx, *v)
+            } else {
+                (best, max)
+            }
+        })
+        .0
+}
+
+/// Prediction of a leaf: its value, or the index of its largest value if it holds several.
+pub(crate) fn leaf_prediction(leaf: &[f64]) -> f64 {
+    match leaf {
+        [value] => *value,
+        values => argmax(values) as f64,
+    }
+}
+
+impl Tree {
+    pub fn fit<T, S: Score<T>>(
+        samples: &impl DataSet,
+        target: &impl Target<T>,
+        tree_config: &TreeConfig,
+        score_fn: &S,
+    ) -> Result<Tree, TreeError> {
+        let rows: Vec<usize> = (0..target.len()).collect();
+        Tree::fit_rows(samples, target, rows, tree_config, score_fn)
+    }
+    /// Fits the tree on `rows` only, a row appearing several times (e.g. in a bootstrap
+    /// sample) counts as many times.
+    pub fn fit_rows<T, S: Score<T>>(
+        samples: &impl DataSet,
+        target: &impl Target<T>,
+        mut rows: Vec<usize>,
+        tree_config: &TreeConfig,
+        score_fn: &S,
+    ) -> Result<Tree, TreeError> {
+        score_fn.validate_target(target)?;
+        let builder = TreeBuilder::new(samples, target, tree_config, score_fn);
+        match tree_config.growth_policy {
+            GrowthPolicy::DepthWise => {
+                let mut n_leaves = 1;
+                builder.build_tree_recursive(&mut rows, None, 0, &mut n_leaves)
+            }
+            GrowthPolicy::LossGuide => builder.build_tree_loss_guide(rows),
+        }
+    }
+    /// Grows the tree level by level and evaluates `metric` on the validation set after
+    /// each level, leaves mapped to the target space by the score. Stops after
+    /// `early_stopping_rounds` levels without improvement and returns the tree truncated
+    /// at the best level, along with the metrics of every level evaluated.
+    /// Depth-wise trees spend their leaf budget level by level, loss guided ones are
+    /// grown in full and then evaluated at every depth.
+    pub fn fit_with_eval<T, S: Score<T>>(
+        train: (&impl DataSet, &impl Target<T>),
+        valid: (&impl DataSet, &impl Target<T>),
+        tree_config: &TreeConfig,
+        score_fn: &S,
+        metric: &impl Metric<T>,
+    ) -> Result<(Tree, EvalHistory), TreeError> {
+        check_early_stopping_rounds(tree_config.early_stopping_rounds);
+        score_fn.validate_target(train.1)?;
+        let builder = TreeBuilder::new(train.0, train.1, tree_config, score_fn);
+        let mut history = EvalHistory::new(metric.higher_is_better());
+        // Records the metrics of a level, returns whether to stop growing
+        let mut evaluate = |tree: &Tree, depth: usize| -> Result<bool, TreeError> {
+            let train_preds = tree.target_predictions(train.0, score_fn)?;
+            let valid_preds = tree.target_predictions(valid.0, score_fn)?;
+            let stages_since_best = history.record(EvalRecord {
+                stage: depth,
+                train: metric.evaluate(train.1, &train_preds),
+                valid: metric.evaluate(valid.1, &valid_preds),
+            });
+            Ok(tree_config
+                .early_stopping_rounds
+                .is_some_and(|rounds| stages_since_best >= rounds))
+        };
+        let mut rows: Vec<usize> = (0..train.1.len()).collect();
+        let tree = match tree_config.growth_policy {
+            GrowthPolicy::DepthWise => {
+                let mut tree = builder.build_leaf(&rows);
+                let mut level = vec![(rows.clone(), None)];
+                let mut n_leaves = 1;
+                let mut depth = 0;
+                let mut stop = evaluate(&tree, depth)?;
+                while !stop {
+                    level = builder.grow_level(&mut tree, level, depth, &mut n_leaves);
+                    if level.is_empty() {
+                        break;
+                    }
+                    depth += 1;
+                    stop = evaluate(&tree, depth)?;
+                }
+                tree
+            }
+            GrowthPolicy::LossGuide => {
+                let tree = builder.build_tree_loss_guide(rows.clone())?;
+                for depth in 0..=tree.depth() {
+                    if evaluate(&builder.truncated(&tree, &mut rows, depth), depth)? {
+                        break;
+                    }
+                }
+                tree
+            }
+        };
+        let best_depth = history
+            .best_record()
+            .expect("The single leaf tree is always evaluated")
+            .stage;
+        Ok((builder.truncated(&tree, &mut rows, best_depth), history))
+    }
+    /// Predictions mapped to the target space by the score the tree was fitted with.
+    fn target_predictions<T>(
+        &self,
+        samples: &impl DataSet,
+        score_fn: &impl Score<T>,
+    ) -> Result<Vec<f64>, TreeError> {
+        Ok(self
+            .predict(samples)?
+            .into_iter()
+            .map(|leaf| score_fn.target_prediction(leaf))
+            .collect())
+    }
+    pub fn depth(&self) -> usize {
+        match (self.left.as_ref(), self.right.as_ref()) {
+            (Some(l), Some(r)) => 1 + l.depth().max(r.depth()),
+            _ => 0,
+        }
+    }
+    pub fn n_leaves(&self) -> usize {
+        match (self.left.as_ref(), self.right.as_ref()) {
+            (Some(l), Some(r)) => l.n_leaves() + r.n_leaves(),
+            _ => 1,
+        }
+    }
+    /// Leaves from left to right, numbered as by `predict_leaf_index`.
+    pub(crate) fn leaves_mut(&mut self) -> Vec<&mut Tree> {
+        if self.left.is_none() || self.right.is_none() {
+            return vec![self];
+        }
+        let mut leaves = Vec::new();
+        for child in [self.left.as_mut(), self.right.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            leaves.extend(child.leaves_mut());
+        }
+        leaves
+    }
+    /// Leaves at `depth` from left to right.
+    fn leaves_at_depth_mut(&mut self, depth: usize) -> Vec<&mut Tree> {
+        if self.left.is_none() || self.right.is_none() {
+            return match depth {
+                0 => vec![self],
+                _ => Vec::new(),
+            };
+        }
+        if depth == 0 {
+            return Vec::new();
+        }
+        let mut leaves = Vec::new();
+        for child in [self.left.as_mut(), self.right.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            leaves.extend(child.leaves_at_depth_mut(depth - 1));
+        }
+        leaves
+    }
+    /// Leaf reached by `sample`, `visit` is called on every split node traversed.
+    fn find_leaf<'a, T: Into<f64> + Copy>(
         &'a self,
-        sample: &'a [(&'a str, Option<T>)],
-    ) -> Result<f64, TreeError> {
-        if let (Some(split_info), Some(l), Some(r)) = (
-            self.split_info.as_ref(),
-            self.left.as_ref(),
-            self.right.as_ref(),
+        sample: &[(&str, Option<T>)],
+        mut visit: impl FnMut(&'a Tree, DecisionStep<'a>),
+    ) -> Result<&'a Tree, TreeError> {
+        let mut node = self;
+        while let (Some(split_info), Some(l), Some(r)) = (
+            node.split_info.as_ref(),
+            node.left.as_ref(),
+            node.right.as_ref(),
         ) {
             let (_, val) = sample
                 .iter()
                 .find(|(name, _)| split_info.name.eq(name))
-                .expect(format!("Feature {} not in dataset", split_info.name).as_str());
-            match val {
-                Some(val) => {
-                    if (*val).into() < split_info.value {
-                        l.predict_single_value(sample)
-                    } else {
-                        r.predict_single_value(sample)
-                    }
-                }
-                None => match split_info.score.null_direction {
-                    loss_fn::split_values::NullDirection::Left => l.predict_single_value(sample),
-                    loss_fn::split_values::NullDirection::Right => r.predict_single_value(sample),
-                },
-            }
-        } else {
-            self.prediction.ok_or(TreeError::NoPredictionInLeaf)
+                .ok_or_else(|| TreeError::CouldNotFindFeature(split_info.name.clone()))?;
+            // NaN values are missing, as when fitting
+            let val = val.map(Into::into).filter(|val: &f64| !val.is_nan());
+            let went_left = match val {
+                Some(val) => split_info.goes_left(val),
+                None => split_info.score.null_direction == NullDirection::Left,
+            };
+            let step = DecisionStep {
+                split_info,
+                went_left,
+                null_direction_taken: val.is_none(),
+            };
+            visit(node, step);
+            node = if went_left { l } else { r };
         }
+        Ok(node)
+    }
+    fn predict_single_value<T: Into<f64> + Copy>(
+        &self,
+        sample: &[(&str, Option<T>)],
+    ) -> Result<&[f64], TreeError> {
+        self.find_leaf(sample, |_, _| {})?
+            .prediction
+            .as_deref()
+            .ok_or(TreeError::NoPredictionInLeaf)
     }
+    /// Leaf value of every sample. Leaves holding several values (e.g. class
+    /// probabilities) predict the index of the largest one.
     pub fn predict(&self, samples: &impl DataSet) -> Result<Vec<f64>, TreeError> {
         samples
             .rows()?
-            .map(|row| self.predict_single_value(row?.as_slice()))
+            .map(|row| {
+                let row = row?;
+                let leaf = self.predict_single_value(row.as_slice())?;
+                Ok(leaf_prediction(leaf))
+            })
+            .collect()
+    }
+    /// All the values of the leaf of every sample.
+    pub fn predict_vector(&self, samples: &impl DataSet) -> Result<Vec<Vec<f64>>, TreeError> {
+        samples
+            .rows()?
+            .map(|row| Ok(self.predict_single_value(row?.as_slice())?.to_vec()))
+            .collect()
+    }
+    /// Splits traversed by every sample from the root to its leaf.
+    pub fn decision_path(
+        &self,
+        samples: &impl DataSet,
+    ) -> Result<Vec<Vec<DecisionStep<'_>>>, TreeError> {
+        samples
+            .rows()?
+            .map(|row| {
+                let mut path = Vec::with_capacity(self.depth());
+                self.find_leaf(row?.as_slice(), |_, step| path.push(step))?;
+                Ok(path)
+            })
             .collect()
     }
 }
@@ -146,13 +623,19 @@ mod tests {
     use loss_fn::{split_values::SplitScore, ScoringFunction};
 
     use super::*;
+    use crate::test_utils::{noisy_step, Lcg};
+    use split::WeightedTarget;
+    use std::borrow::Cow;
     use std::collections::HashMap;
 
     #[test]
     fn test_tree() {
         let data = HashMap::from([("F1".to_string(), vec![1., 2., 3.])]);
         let target = vec![true, false, false];
-        let tree_config = TreeConfig { max_depth: 2 };
+        let tree_config = TreeConfig {
+            max_depth: 2,
+            ..Default::default()
+        };
         let score_fn = ScoringFunction::Gini(loss_fn::Gini);
         let tree = Tree::fit(&data, &target, &tree_config, &score_fn);
         let output_tree = Tree {
@@ -168,15 +651,18 @@ mod tests {
                 split_info: None,
                 left: None,
                 right: None,
-                prediction: Some(1.0),
+                prediction: Some(vec![1.0]),
+                cover: 1.,
             })),
             right: Some(Box::new(Tree {
                 split_info: None,
                 left: None,
                 right: None,
-                prediction: Some(0.0),
+                prediction: Some(vec![0.0]),
+                cover: 2.,
             })),
             prediction: None,
+            cover: 3.,
         };
         assert_eq!(
             output_tree,
@@ -188,7 +674,10 @@ mod tests {
     fn test_with_logit() {
         let data = HashMap::from([("F1".to_string(), vec![1., 2., 3.])]);
         let target = vec![true, false, false];
-        let tree_config = TreeConfig { max_depth: 2 };
+        let tree_config = TreeConfig {
+            max_depth: 2,
+            ..Default::default()
+        };
         let score_fn = ScoringFunction::Logit(loss_fn::Logit::new(0.5));
         let tree = Tree::fit(&data, &target, &tree_config, &score_fn);
         let output_tree = Tree {
@@ -204,15 +693,18 @@ mod tests {
                 split_info: None,
                 left: None,
                 right: None,
-                prediction: Some(2.0),
+                prediction: Some(vec![2.0]),
+                cover: 1.,
             })),
             right: Some(Box::new(Tree {
                 split_info: None,
                 left: None,
                 right: None,
-                prediction: Some(-2.0),
+                prediction: Some(vec![-2.0]),
+                cover: 2.,
             })),
             prediction: None,
+            cover: 3.,
         };
         assert_eq!(
             output_tree,
@@ -235,18 +727,480 @@ mod tests {
                 split_info: None,
                 left: None,
                 right: None,
-                prediction: Some(2.0),
+                prediction: Some(vec![2.0]),
+                cover: 1.,
             })),
             right: Some(Box::new(Tree {
                 split_info: None,
                 left: None,
                 right: None,
-                prediction: Some(-2.0),
+                prediction: Some(vec![-2.0]),
+                cover: 2.,
             })),
             prediction: None,
+            cover: 3.,
         };
         let dataset = HashMap::from([("F1".to_string(), vec![1., 3.])]);
         let pred = output_tree.predict(&dataset).unwrap();
         assert_eq!(vec![2., -2.], pred, "Wrong predictions")
     }
+    #[test]
+    fn test_predict_par() {
+        let data = HashMap::from([
+            ("F1".to_string(), vec![1., 2., 3., 4., f64::NAN, 6.]),
+            ("F2".to_string(), vec![0., 1., 0., 1., 0., 1.]),
+        ]);
+        let target = vec![true, true, false, true, false, false];
+        let tree_config = TreeConfig {
+            max_depth: 3,
+            ..Default::default()
+        };
+        let tree = Tree::fit(&data, &target, &tree_config, &loss_fn::Gini).unwrap();
+        assert_eq!(
+            tree.predict(&data).unwrap(),
+            tree.predict_par(&data).unwrap()
+        );
+        assert!(
+            matches!(data.column("F1"), Some(Cow::Borrowed(_))),
+            "f64 columns should be borrowed"
+        );
+        let f32_data: HashMap<String, Vec<f32>> = data
+            .iter()
+            .map(|(name, values)| {
+                (
+                    name.clone(),
+                    values.as_slice().iter().map(|v| *v as f32).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            tree.predict(&data).unwrap(),
+            tree.predict_par(&f32_data).unwrap()
+        );
+
+        // Ragged columns are an error, as for the sequential predictions
+        let mut ragged = data.clone();
+        ragged.get_mut(tree.feature_names()[0]).unwrap().pop();
+        assert!(matches!(
+            tree.predict(&ragged),
+            Err(TreeError::DataSetRowsError(
+                split::DataSetRowsError::IllFormedColumn(_, 5)
+            ))
+        ));
+        assert!(matches!(
+            tree.predict_par(&ragged),
+            Err(TreeError::DataSetRowsError(
+                split::DataSetRowsError::IllFormedColumn(_, 5)
+            ))
+        ));
+    }
+    #[test]
+    fn test_single_split_leaf_index() {
+        let data = HashMap::from([("F1".to_string(), vec![1., 3.])]);
+        let tree_config = TreeConfig {
+            max_depth: 1,
+            ..Default::default()
+        };
+        let tree = Tree::fit(&data, &vec![2., -2.], &tree_config, &loss_fn::SquaredError).unwrap();
+        let split_info = tree.split_info.as_ref().unwrap();
+        assert_eq!(NullDirection::Left, split_info.score.null_direction);
+
+        let dataset = HashMap::from([("F1".to_string(), vec![1., 3., f64::NAN])]);
+        let leaf_indices = tree.predict_leaf_index(&dataset).unwrap();
+        assert_eq!(vec![0, 1, 0], leaf_indices, "Wrong leaf indices");
+        let paths = tree.decision_path(&dataset).unwrap();
+        let steps: Vec<(bool, bool)> = paths
+            .iter()
+            .map(|path| {
+                assert_eq!(1, path.len(), "Wrong path length");
+                assert_eq!(split_info, path[0].split_info, "Wrong split");
+                (path[0].went_left, path[0].null_direction_taken)
+            })
+            .collect();
+        assert_eq!(vec![(true, false), (false, false), (true, true)], steps);
+    }
+    #[test]
+    fn test_leaf_index_and_decision_path() {
+        let data = HashMap::from([
+            ("F1".to_string(), vec![1., 2., 3., 4., 5., 6., f64::NAN, 8.]),
+            ("F2".to_string(), vec![0., 1., 0., 1., f64::NAN, 1., 0., 1.]),
+        ]);
+        let target = vec![1., 2., 1., 2., 10., 12., 10., 12.];
+        let tree_config = TreeConfig {
+            max_depth: 2,
+            ..Default::default()
+        };
+        let tree = Tree::fit(&data, &target, &tree_config, &loss_fn::SquaredError).unwrap();
+        let leaf_indices = tree.predict_leaf_index(&data).unwrap();
+        let preds = tree.predict(&data).unwrap();
+        let paths = tree.decision_path(&data).unwrap();
+        let mut leaf_preds = vec![None; tree.n_leaves()];
+        for ((leaf_index, pred), path) in leaf_indices.iter().zip(preds).zip(paths.iter()) {
+            // Every row of a leaf shares its prediction and its path
+            let (leaf_pred, leaf_path) = leaf_preds[*leaf_index].get_or_insert((pred, path));
+            assert_eq!(*leaf_pred, pred, "Leaf index does not identify the leaf");
+            let directions = |path: &[DecisionStep]| -> Vec<bool> {
+                path.iter().map(|step| step.went_left).collect()
+            };
+            assert_eq!(directions(leaf_path), directions(path), "Wrong path");
+        }
+        assert!(leaf_preds.iter().all(Option::is_some), "Unreachable leaf");
+        for (row, path) in paths.iter().enumerate() {
+            for step in path.iter() {
+                let value = data[&step.split_info.name][row];
+                assert_eq!(value.is_nan(), step.null_direction_taken, "Wrong null flag");
+            }
+        }
+    }
+    #[test]
+    fn test_depth_and_leaves_limits() {
+        let data = HashMap::from([("F1".to_string(), vec![1., 2., 3., 4., 5., 6., 7., 8.])]);
+        let target = vec![true, true, false, false, true, true, false, false];
+        let score_fn = ScoringFunction::Gini(loss_fn::Gini);
+        let deep_config = TreeConfig {
+            max_depth: 10,
+            ..Default::default()
+        };
+        let deep_tree = Tree::fit(&data, &target, &deep_config, &score_fn).unwrap();
+        assert_eq!(
+            4,
+            deep_tree.n_leaves(),
+            "Deep tree should isolate every group"
+        );
+
+        let shallow_config = TreeConfig {
+            max_depth: 1,
+            ..Default::default()
+        };
+        let shallow_tree = Tree::fit(&data, &target, &shallow_config, &score_fn).unwrap();
+        assert_eq!(1, shallow_tree.depth(), "max_depth not respected");
+
+        let leaves_config = TreeConfig {
+            max_depth: 10,
+            max_leaf_nodes: Some(3),
+            ..Default::default()
+        };
+        let leaves_tree = Tree::fit(&data, &target, &leaves_config, &score_fn).unwrap();
+        assert_eq!(3, leaves_tree.n_leaves(), "max_leaf_nodes not respected");
+    }
+    #[test]
+    fn test_leaf_constraints() {
+        let data = HashMap::from([("F1".to_string(), vec![1., 2., 3.])]);
+        let target = vec![true, false, false];
+        let leaf = Tree {
+            split_info: None,
+            left: None,
+            right: None,
+            prediction: Some(vec![1. / 3.]),
+            cover: 3.,
+        };
+
+        let gini = ScoringFunction::Gini(loss_fn::Gini);
+        let min_leaf_config = TreeConfig {
+            max_depth: 2,
+            min_samples_leaf: 2,
+            ..Default::default()
+        };
+        let tree = Tree::fit(&data, &target, &min_leaf_config, &gini).unwrap();
+        assert_eq!(leaf, tree, "min_samples_leaf not respected");
+
+        let min_split_config = TreeConfig {
+            max_depth: 2,
+            min_samples_split: 4,
+            ..Default::default()
+        };
+        let tree = Tree::fit(&data, &target, &min_split_config, &gini).unwrap();
+        assert_eq!(leaf, tree, "min_samples_split not respected");
+
+        let min_decrease_config = TreeConfig {
+            max_depth: 2,
+            min_impurity_decrease: 0.5,
+            ..Default::default()
+        };
+        let tree = Tree::fit(&data, &target, &min_decrease_config, &gini).unwrap();
+        assert_eq!(leaf, tree, "min_impurity_decrease not respected");
+
+        // Each row has hessian 0.25, the single true row cannot be isolated
+        let logit = ScoringFunction::Logit(loss_fn::Logit::new(0.5));
+        let min_weight_config = TreeConfig {
+            max_depth: 2,
+            min_child_weight: 0.5,
+            ..Default::default()
+        };
+        let tree = Tree::fit(&data, &target, &min_weight_config, &logit).unwrap();
+        assert!(tree.split_info.is_none(), "min_child_weight not respected");
+    }
+    #[test]
+    fn test_histogram_subtraction_matches_recompute() {
+        // Deterministic pseudo random data with missing values
+        let mut rng = Lcg::new(42);
+        let mut data = HashMap::new();
+        for name in ["F1", "F2", "F3"] {
+            let values: Vec<f64> = (0..300)
+                .map(|_| match rng.uniform() < 0.1 {
+                    true => f64::NAN,
+                    false => (rng.uniform() * 50.).floor(),
+                })
+                .collect();
+            data.insert(name.to_string(), values);
+        }
+        let target: Vec<bool> = (0..300)
+            .map(|idx| (data["F1"][idx] + data["F2"][idx]) > 45. || rng.uniform() < 0.2)
+            .collect();
+        let binned = split::binned::BinnedDataSet::new(&data, 16).unwrap();
+
+        for growth_policy in [GrowthPolicy::DepthWise, GrowthPolicy::LossGuide] {
+            for score_fn in [
+                ScoringFunction::Gini(loss_fn::Gini),
+                ScoringFunction::Logit(loss_fn::Logit::new(0.5)),
+            ] {
+                let subtraction_config = TreeConfig {
+                    max_depth: 5,
+                    max_leaf_nodes: Some(12),
+                    growth_policy,
+                    ..Default::default()
+                };
+                let recompute_config = TreeConfig {
+                    max_depth: 5,
+                    max_leaf_nodes: Some(12),
+                    growth_policy,
+                    recompute_histograms: true,
+                    ..Default::default()
+                };
+                let subtraction = Tree::fit(&data, &target, &subtraction_config, &score_fn);
+                let recompute = Tree::fit(&data, &target, &recompute_config, &score_fn);
+                assert_eq!(recompute.unwrap(), subtraction.unwrap(), "Raw trees differ");
+
+                let subtraction = Tree::fit(&binned, &target, &subtraction_config, &score_fn);
+                let recompute = Tree::fit(&binned, &target, &recompute_config, &score_fn);
+                let subtraction = subtraction.unwrap();
+                assert!(subtraction.depth() > 2, "Tree should not be trivial");
+                assert_eq!(recompute.unwrap(), subtraction, "Binned trees differ");
+            }
+        }
+    }
+    /// Follows the derived child of every split from the root, checking that the
+    /// histograms of both children have `expected_bins(n_rows)` bins. Returns the depth.
+    fn check_children_bins(
+        samples: &impl DataSet,
+        target: &Vec<bool>,
+        expected_bins: impl Fn(usize) -> usize,
+    ) -> usize {
+        let tree_config = TreeConfig {
+            max_depth: 64,
+            ..Default::default()
+        };
+        let builder = TreeBuilder::new(samples, target, &tree_config, &loss_fn::Gini);
+        let bins =
+            |histograms: &Option<Histograms<_>>| histograms.as_ref().unwrap()["F1"].bins.len();
+        let mut all_rows: Vec<usize> = (0..target.len()).collect();
+        let (mut rows, mut histograms, mut depth) = (all_rows.as_mut_slice(), None, 0);
+        while let Some(node_split) = builder.node_split(rows, histograms, depth) {
+            let null_direction = node_split.split_info.score.null_direction;
+            let n_left = partition_rows(rows, &node_split.mask, null_direction);
+            let (left_rows, right_rows) = rows.split_at_mut(n_left);
+            let (left_hist, right_hist) = builder.children_histograms(
+                node_split.histograms,
+                left_rows,
+                right_rows,
+                depth + 1,
+            );
+            assert_eq!(
+                expected_bins(left_rows.len()),
+                bins(&left_hist),
+                "Wrong left bins"
+            );
+            assert_eq!(
+                expected_bins(right_rows.len()),
+                bins(&right_hist),
+                "Wrong right bins"
+            );
+            (rows, histograms) = match left_rows.len() > right_rows.len() {
+                true => (left_rows, left_hist),
+                false => (right_rows, right_hist),
+            };
+            depth += 1;
+        }
+        depth
+    }
+    #[test]
+    fn test_children_histograms_keep_their_bins() {
+        // Distinct values: the root has one bin per row, nodes deep down only their own
+        let n_rows = 256;
+        let values: Vec<f64> = (0..n_rows).map(|v| ((v * 37) % n_rows) as f64).collect();
+        let target: Vec<bool> = (0..n_rows)
+            .map(|v| (v * 37) % n_rows / 8 % 2 == 0)
+            .collect();
+        let data = HashMap::from([("F1".to_string(), values)]);
+        let depth = check_children_bins(&data, &target, |n_rows| n_rows);
+        assert!(depth > 4, "Tree should be deep");
+
+        // 4 values per bin, splits never cut a bin
+        let binned = split::binned::BinnedDataSet::new(&data, 64).unwrap();
+        let depth = check_children_bins(&binned, &target, |n_rows| n_rows.div_ceil(4));
+        assert!(depth > 2, "Tree should be deep");
+    }
+    #[test]
+    fn test_fit_with_eval() {
+        // Labels only depend on F1 < 0.5, 30% of them are flipped
+        let mut rng = Lcg::new(11);
+        let (train_data, train_target) = noisy_step(&mut rng, 200, 0.3);
+        let (valid_data, valid_target) = noisy_step(&mut rng, 200, 0.3);
+        let score_fn = ScoringFunction::Gini(loss_fn::Gini);
+        let tree_config = TreeConfig {
+            max_depth: 8,
+            ..Default::default()
+        };
+        let (tree, history) = Tree::fit_with_eval(
+            (&train_data, &train_target),
+            (&valid_data, &valid_target),
+            &tree_config,
+            &score_fn,
+            &crate::metrics::Accuracy,
+        )
+        .unwrap();
+        let stages: Vec<usize> = history.records.iter().map(|r| r.stage).collect();
+        assert_eq!(
+            (0..=8).collect::<Vec<_>>(),
+            stages,
+            "Every level is evaluated"
+        );
+        let best = history.best_record().unwrap();
+        assert_eq!(
+            best.stage,
+            tree.depth(),
+            "Tree not truncated to the best level"
+        );
+        let last = history.records.last().unwrap();
+        assert!(
+            last.train > best.train,
+            "Deeper trees should fit train better"
+        );
+        assert!(last.valid < best.valid, "Deeper trees should overfit");
+        let full_tree = Tree::fit(&train_data, &train_target, &tree_config, &score_fn).unwrap();
+        assert!(
+            has_prefix(&full_tree, &tree),
+            "Levels are not grown as by fit"
+        );
+
+        // Growth stops two levels after the best one, the same tree is kept
+        let early_stopping_config = TreeConfig {
+            early_stopping_rounds: Some(2),
+            ..tree_config
+        };
+        let (early_stopped_tree, early_stopped_history) = Tree::fit_with_eval(
+            (&train_data, &train_target),
+            (&valid_data, &valid_target),
+            &early_stopping_config,
+            &score_fn,
+            &crate::metrics::Accuracy,
+        )
+        .unwrap();
+        assert_eq!(
+            &history.records[..best.stage + 3],
+            early_stopped_history.records,
+            "Growth did not stop two levels after the best one"
+        );
+        assert_eq!(tree, early_stopped_tree, "Wrong early stopped tree");
+
+        // Every level is a prefix of the same tree, even when grown best first
+        let loss_guide_config = TreeConfig {
+            growth_policy: GrowthPolicy::LossGuide,
+            max_leaf_nodes: Some(12),
+            ..tree_config
+        };
+        let (loss_guide_tree, _) = Tree::fit_with_eval(
+            (&train_data, &train_target),
+            (&valid_data, &valid_target),
+            &loss_guide_config,
+            &score_fn,
+            &crate::metrics::Accuracy,
+        )
+        .unwrap();
+        let full_tree =
+            Tree::fit(&train_data, &train_target, &loss_guide_config, &score_fn).unwrap();
+        assert!(
+            has_prefix(&full_tree, &loss_guide_tree),
+            "Best level is not a prefix of the full tree"
+        );
+
+        // Metrics are computed on probabilities, not on the log odds of the leaves
+        let logit = loss_fn::Logit::new(0.5);
+        let (logit_tree, logit_history) = Tree::fit_with_eval(
+            (&train_data, &train_target),
+            (&valid_data, &valid_target),
+            &tree_config,
+            &logit,
+            &crate::metrics::LogLoss,
+        )
+        .unwrap();
+        let probabilities: Vec<f64> = logit_tree
+            .predict(&valid_data)
+            .unwrap()
+            .into_iter()
+            .map(|leaf| logit.target_prediction(leaf))
+            .collect();
+        assert!(probabilities.iter().all(|p| p > 0. && p < 1.));
+        assert_eq!(
+            crate::metrics::LogLoss.evaluate(&valid_target, &probabilities),
+            logit_history.best_record().unwrap().valid,
+            "Wrong log loss"
+        );
+    }
+    /// Whether the splits of `prefix` are the top splits of `tree`.
+    fn has_prefix(tree: &Tree, prefix: &Tree) -> bool {
+        match (&prefix.left, &prefix.right) {
+            (Some(left), Some(right)) => {
+                tree.split_info == prefix.split_info
+                    && has_prefix(tree.left.as_ref().unwrap(), left)
+                    && has_prefix(tree.right.as_ref().unwrap(), right)
+            }
+            _ => true,
+        }
+    }
+    #[test]
+    fn test_weights_match_duplicated_rows() {
+        let data = HashMap::from([("F1".to_string(), vec![1., 2., 3., 4., 5.])]);
+        let target = vec![true, false, true, false, false];
+        let weighted = WeightedTarget::new(target.clone(), vec![1., 3., 1., 2., 1.]);
+        let duplicated_data =
+            HashMap::from([("F1".to_string(), vec![1., 2., 2., 2., 3., 4., 4., 5.])]);
+        let duplicated = vec![true, false, false, false, true, false, false, false];
+        let tree_config = TreeConfig {
+            max_depth: 2,
+            ..Default::default()
+        };
+        for score_fn in [
+            ScoringFunction::Gini(loss_fn::Gini),
+            ScoringFunction::Entropy(loss_fn::Entropy),
+            ScoringFunction::Logit(loss_fn::Logit::new(0.5)),
+        ] {
+            let weighted_tree = Tree::fit(&data, &weighted, &tree_config, &score_fn).unwrap();
+            let duplicated_tree =
+                Tree::fit(&duplicated_data, &duplicated, &tree_config, &score_fn).unwrap();
+            assert_eq!(
+                duplicated_tree, weighted_tree,
+                "Weights differ from duplicated rows for {}",
+                score_fn
+            );
+        }
+        let unweighted_tree = Tree::fit(
+            &data,
+            &target,
+            &tree_config,
+            &ScoringFunction::Gini(loss_fn::Gini),
+        );
+        assert_ne!(
+            unweighted_tree.unwrap().split_info,
+            Tree::fit(
+                &data,
+                &weighted,
+                &tree_config,
+                &ScoringFunction::Gini(loss_fn::Gini)
+            )
+            .unwrap()
+            .split_info,
+            "Weights should change the split"
+        );
+    }
 }
@@ -0,0 +1,178 @@
+use super::Tree;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Version of the layout of saved trees, bumped on every incompatible change.
+pub const FORMAT_VERSION: u32 = 2;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PersistenceError {
+    #[error("Persistence Error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Persistence Error: invalid JSON, {0}")]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "binary")]
+    #[error("Persistence Error: invalid binary, {0}")]
+    Binary(#[from] bincode::Error),
+    #[error("Persistence Error: format version {found} is not supported, expected {expected}")]
+    IncompatibleVersion { found: u32, expected: u32 },
+}
+
+/// Layout of a saved tree, the version comes first so that it can be read alone.
+#[derive(Serialize, Deserialize)]
+struct SavedTree<T> {
+    format_version: u32,
+    tree: T,
+}
+
+#[derive(Deserialize)]
+struct VersionHeader {
+    format_version: u32,
+}
+
+fn check_version(format_version: u32) -> Result<(), PersistenceError> {
+    match format_version == FORMAT_VERSION {
+        true => Ok(()),
+        false => Err(PersistenceError::IncompatibleVersion {
+            found: format_version,
+            expected: FORMAT_VERSION,
+        }),
+    }
+}
+
+impl Tree {
+    /// JSON of the tree along with the format version.
+    pub fn to_json(&self) -> Result<String, PersistenceError> {
+        let saved = SavedTree {
+            format_version: FORMAT_VERSION,
+            tree: self,
+        };
+        Ok(serde_json::to_string(&saved)?)
+    }
+    /// Reads a tree from `to_json`, failing on other format versions.
+    pub fn from_json(json: &str) -> Result<Tree, PersistenceError> {
+        let header: VersionHeader = serde_json::from_str(json)?;
+        check_version(header.format_version)?;
+        let saved: SavedTree<Tree> = serde_json::from_str(json)?;
+        Ok(saved.tree)
+    }
+    /// Writes the tree to `path` as JSON.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), PersistenceError> {
+        Ok(fs::write(path, self.to_json()?)?)
+    }
+    /// Reads a tree written by `save`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Tree, PersistenceError> {
+        Tree::from_json(&fs::read_to_string(path)?)
+    }
+    /// Binary encoding of the tree, the format version takes the first 4 bytes.
+    #[cfg(feature = "binary")]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, PersistenceError> {
+        let saved = SavedTree {
+            format_version: FORMAT_VERSION,
+            tree: self,
+        };
+        Ok(bincode::serialize(&saved)?)
+    }
+    /// Reads a tree from `to_bytes`, failing on other format versions.
+    #[cfg(feature = "binary")]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Tree, PersistenceError> {
+        let format_version: u32 = bincode::deserialize(bytes)?;
+        check_version(format_version)?;
+        let saved: SavedTree<Tree> = bincode::deserialize(bytes)?;
+        Ok(saved.tree)
+    }
+    /// Writes the tree to `path` in the compact binary format.
+    #[cfg(feature = "binary")]
+    pub fn save_binary(&self, path: impl AsRef<Path>) -> Result<(), PersistenceError> {
+        Ok(fs::write(path, self.to_bytes()?)?)
+    }
+    /// Reads a tree written by `save_binary`.
+    #[cfg(feature = "binary")]
+    pub fn load_binary(path: impl AsRef<Path>) -> Result<Tree, PersistenceError> {
+        Tree::from_bytes(&fs::read(path)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tree::loss_fn::{Gini, ScoringFunction};
+    use crate::tree::split::categorical::{CategoricalFeature, MixedDataSet};
+    use crate::tree::TreeConfig;
+    use std::collections::HashMap;
+
+    fn fitted_trees() -> Vec<(Tree, MixedDataSet)> {
+        let colors = ["red", "blue", "green", "red", "blue", "green"];
+        let data = MixedDataSet::new()
+            .with_numerical("F1", vec![1., f64::NAN, 3., 4., 5., f64::NAN])
+            .with_categorical("color", CategoricalFeature::from_strings(&colors.map(Some)));
+        let target = vec![true, false, false, true, true, false];
+        let tree_config = TreeConfig {
+            max_depth: 3,
+            ..Default::default()
+        };
+        let score_fn = ScoringFunction::Gini(Gini);
+        let tree = Tree::fit(&data, &target, &tree_config, &score_fn).unwrap();
+        let leaf = Tree::fit(&data, &vec![true; 6], &tree_config, &score_fn).unwrap();
+        vec![(tree, data.clone()), (leaf, data)]
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        for (tree, data) in fitted_trees() {
+            let json = tree.to_json().unwrap();
+            let loaded = Tree::from_json(&json).unwrap();
+            assert_eq!(tree, loaded, "Tree changed by the round trip");
+            assert_eq!(tree.predict(&data).unwrap(), loaded.predict(&data).unwrap());
+        }
+        let (tree, _) = fitted_trees().remove(0);
+        let path = std::env::temp_dir().join("kyt_test_json_round_trip.json");
+        tree.save(&path).unwrap();
+        assert_eq!(tree, Tree::load(&path).unwrap(), "Wrong tree loaded");
+        fs::remove_file(path).unwrap();
+    }
+    #[test]
+    fn test_incompatible_version() {
+        let (tree, _) = fitted_trees().remove(0);
+        let json = tree.to_json().unwrap().replacen(
+            &format!("\"format_version\":{}", FORMAT_VERSION),
+            "\"format_version\":0",
+            1,
+        );
+        match Tree::from_json(&json) {
+            Err(PersistenceError::IncompatibleVersion { found, expected }) => {
+                assert_eq!((0, FORMAT_VERSION), (found, expected))
+            }
+            other => panic!("Expected a version error, got {:?}", other),
+        }
+        let missing = HashMap::from([("tree", "null")]);
+        let json = serde_json::to_string(&missing).unwrap();
+        assert!(matches!(
+            Tree::from_json(&json),
+            Err(PersistenceError::Json(_))
+        ));
+    }
+    #[cfg(feature = "binary")]
+    #[test]
+    fn test_binary_round_trip() {
+        for (tree, data) in fitted_trees() {
+            let bytes = tree.to_bytes().unwrap();
+            assert!(bytes.len() < tree.to_json().unwrap().len(), "Not compact");
+            let loaded = Tree::from_bytes(&bytes).unwrap();
+            assert_eq!(tree, loaded, "Tree changed by the round trip");
+            assert_eq!(tree.predict(&data).unwrap(), loaded.predict(&data).unwrap());
+        }
+        let (tree, _) = fitted_trees().remove(0);
+        let mut bytes = tree.to_bytes().unwrap();
+        bytes[..4].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            Tree::from_bytes(&bytes),
+            Err(PersistenceError::IncompatibleVersion { .. })
+        ));
+        let path = std::env::temp_dir().join("kyt_test_binary_round_trip.bin");
+        tree.save_binary(&path).unwrap();
+        assert_eq!(tree, Tree::load_binary(&path).unwrap(), "Wrong tree loaded");
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,378 @@
+use super::loss_fn::split_values::NullDirection;
+use super::split::DataSet;
+use super::{Tree, TreeError};
+
+/// Feature of the path of unique features followed by TreeSHAP, along with the fraction
+/// of the cover of the path that goes through it when the feature is missing (`zero`) or
+/// known (`one`), and the weight of the subsets of the features of the path.
+#[derive(Debug, Clone, Copy)]
+struct PathElement {
+    /// Column of the feature, `None` for the root of the path.
+    feature: Option<usize>,
+    zero: f64,
+    one: f64,
+    weight: f64,
+}
+
+/// Adds a feature to the path, updating the weights of the subsets of every size.
+fn extend_path(path: &mut Vec<PathElement>, zero: f64, one: f64, feature: Option<usize>) {
+    let depth = path.len();
+    path.push(PathElement {
+        feature,
+        zero,
+        one,
+        weight: if depth == 0 { 1. } else { 0. },
+    });
+    let len = (depth + 1) as f64;
+    for i in (0..depth).rev() {
+        path[i + 1].weight += one * path[i].weight * (i + 1) as f64 / len;
+        path[i].weight *= zero * (depth - i) as f64 / len;
+    }
+}
+
+/// Removes the `idx`-th feature from the path, undoing `extend_path`.
+fn unwind_path(path: &mut Vec<PathElement>, idx: usize) {
+    let depth = path.len() - 1;
+    let PathElement { zero, one, .. } = path[idx];
+    let len = (depth + 1) as f64;
+    let mut next = path[depth].weight;
+    for i in (0..depth).rev() {
+        match one != 0. {
+            true => {
+                let weight = path[i].weight;
+                path[i].weight = next * len / ((i + 1) as f64 * one);
+                next = weight - path[i].weight * zero * (depth - i) as f64 / len;
+            }
+            false => path[i].weight = path[i].weight * len / (zero * (depth - i) as f64),
+        }
+    }
+    // Weights stay in place, only the features are shifted
+    for i in idx..depth {
+        path[i].feature = path[i + 1].feature;
+        path[i].zero = path[i + 1].zero;
+        path[i].one = path[i + 1].one;
+    }
+    path.pop();
+}
+
+/// Total weight of the path once the `idx`-th feature is unwound, without unwinding it.
+fn unwound_sum(path: &[PathElement], idx: usize) -> f64 {
+    let depth = path.len() - 1;
+    let PathElement { zero, one, .. } = path[idx];
+    let mut total = 0.;
+    match one != 0. {
+        true => {
+            let mut next = path[depth].weight;
+            for i in (0..depth).rev() {
+                let weight = next / ((i + 1) as f64 * one);
+                total += weight;
+                next = path[i].weight - weight * zero * (depth - i) as f64;
+            }
+        }
+        false => {
+            for i in (0..depth).rev() {
+                total += path[i].weight / (zero * (depth - i) as f64);
+            }
+        }
+    }
+    total * (depth + 1) as f64
+}
+
+impl Tree {
+    /// Value of a leaf whose prediction is a single value.
+    fn single_value(&self) -> Result<f64, TreeError> {
+        match self.prediction.as_deref() {
+            Some([value]) => Ok(*value),
+            Some(values) => Err(TreeError::MultiValueLeaf(values.len())),
+            None => Err(TreeError::NoPredictionInLeaf),
+        }
+    }
+    /// Sum of the leaf values weighted by their cover.
+    fn covered_leaf_sum(&self) -> Result<f64, TreeError> {
+        match (self.left.as_ref(), self.right.as_ref()) {
+            (Some(l), Some(r)) => Ok(l.covered_leaf_sum()? + r.covered_leaf_sum()?),
+            _ => Ok(self.cover * self.single_value()?),
+        }
+    }
+    /// Mean prediction over the training rows, the baseline of `shap_values`.
+    /// Leaves must hold a single value.
+    pub fn expected_value(&self) -> Result<f64, TreeError> {
+        Ok(self.covered_leaf_sum()? / self.cover)
+    }
+    /// Adds the contributions of the leaves under `self` to `phi`, `path` holds the
+    /// features of the splits between the root and `self`.
+    fn tree_shap(
+        &self,
+        row: &[Option<f64>],
+        feature_names: &[&str],
+        phi: &mut [f64],
+        mut path: Vec<PathElement>,
+        (zero, one, feature): (f64, f64, Option<usize>),
+    ) -> Result<(), TreeError> {
+        extend_path(&mut path, zero, one, feature);
+        let (Some(split_info), Some(l), Some(r)) = (
+            self.split_info.as_ref(),
+            self.left.as_ref(),
+            self.right.as_ref(),
+        ) else {
+            let value = self.single_value()?;
+            for (idx, element) in path.iter().enumerate().skip(1) {
+                let weight = unwound_sum(&path, idx);
+                if let Some(feature) = element.feature {
+                    phi[feature] += weight * (element.one - element.zero) * value;
+                }
+            }
+            return Ok(());
+        };
+        let feature = feature_names
+            .binary_search(&split_info.name.as_str())
+            .map_err(|_| TreeError::CouldNotFindFeature(split_info.name.clone()))?;
+        let went_left = match row[feature] {
+            Some(val) => split_info.goes_left(val),
+            None => split_info.score.null_direction == NullDirection::Left,
+        };
+        let (hot, cold) = if went_left { (l, r) } else { (r, l) };
+        // A feature split on twice only counts once along the path
+        let (mut zero, mut one) = (1., 1.);
+        if let Some(idx) = path.iter().position(|e| e.feature == Some(feature)) {
+            (zero, one) = (path[idx].zero, path[idx].one);
+            unwind_path(&mut path, idx);
+        }
+        let hot_zero = zero * hot.cover / self.cover;
+        let cold_zero = zero * cold.cover / self.cover;
+        hot.tree_shap(
+            row,
+            feature_names,
+            phi,
+            path.clone(),
+            (hot_zero, one, Some(feature)),
+        )?;
+        cold.tree_shap(
+            row,
+            feature_names,
+            phi,
+            path,
+            (cold_zero, 0., Some(feature)),
+        )
+    }
+    /// Exact path-dependent TreeSHAP contributions of every feature of every sample, in
+    /// the order of the sorted feature names of `samples`: features the tree does not
+    /// split on contribute 0. Contributions sum to the prediction minus `expected_value`,
+    /// missing values follow the null direction as in `predict`.
+    pub fn shap_values(&self, samples: &impl DataSet) -> Result<Vec<Vec<f64>>, TreeError> {
+        samples
+            .rows()?
+            .map(|row| {
+                let mut row = row?;
+                row.sort_by_key(|(name, _)| *name);
+                let (feature_names, values): (Vec<&str>, Vec<Option<f64>>) = row
+                    .into_iter()
+                    .map(|(name, val)| (name, val.map(Into::into).filter(|val| !val.is_nan())))
+                    .unzip();
+                let mut phi = vec![0.; feature_names.len()];
+                let path = Vec::with_capacity(self.depth() + 1);
+                self.tree_shap(&values, &feature_names, &mut phi, path, (1., 1., None))?;
+                Ok(phi)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::Lcg;
+    use crate::tree::growth::GrowthPolicy;
+    use crate::tree::loss_fn::{Gini, ScoringFunction, SquaredError};
+    use crate::tree::split::categorical::{CategoricalFeature, MixedDataSet};
+    use crate::tree::split::WeightedTarget;
+    use crate::tree::TreeConfig;
+    use std::collections::HashMap;
+
+    /// Expected prediction when only the features in `known` are known, unknown features
+    /// follow both children weighted by their cover.
+    fn conditional_expectation(
+        node: &Tree,
+        row: &[Option<f64>],
+        names: &[&str],
+        known: usize,
+    ) -> f64 {
+        let (Some(split_info), Some(l), Some(r)) = (
+            node.split_info.as_ref(),
+            node.left.as_ref(),
+            node.right.as_ref(),
+        ) else {
+            return node.single_value().unwrap();
+        };
+        let feature = names.binary_search(&split_info.name.as_str()).unwrap();
+        let expectation = |child: &Tree| conditional_expectation(child, row, names, known);
+        match known & (1 << feature) != 0 {
+            true => {
+                let went_left = match row[feature] {
+                    Some(val) => split_info.goes_left(val),
+                    None => split_info.score.null_direction == NullDirection::Left,
+                };
+                expectation(if went_left { l } else { r })
+            }
+            false => (l.cover * expectation(l) + r.cover * expectation(r)) / node.cover,
+        }
+    }
+
+    /// Shapley values of the conditional expectations, by enumerating the subsets.
+    fn brute_force_shap(tree: &Tree, row: &[Option<f64>]) -> Vec<f64> {
+        let names = tree.feature_names();
+        let n = names.len();
+        let factorial = |k: usize| (1..=k).product::<usize>() as f64;
+        (0..n)
+            .map(|feature| {
+                (0..1usize << n)
+                    .filter(|subset| subset & (1 << feature) == 0)
+                    .map(|subset| {
+                        let size = subset.count_ones() as usize;
+                        let weight = factorial(size) * factorial(n - size - 1) / factorial(n);
+                        let with =
+                            conditional_expectation(tree, row, &names, subset | (1 << feature));
+                        let without = conditional_expectation(tree, row, &names, subset);
+                        weight * (with - without)
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+
+    fn lcg_data(n_rows: usize) -> (HashMap<String, Vec<f64>>, Vec<f64>) {
+        let mut rng = Lcg::new(7);
+        let mut data: HashMap<String, Vec<f64>> = HashMap::new();
+        let mut target = Vec::with_capacity(n_rows);
+        for row in 0..n_rows {
+            let values = [rng.uniform(), rng.uniform(), rng.uniform()];
+            for (idx, value) in values.iter().enumerate() {
+                // Some missing values so that the null directions are exercised
+                let value = if row % 11 == idx { f64::NAN } else { *value };
+                data.entry(format!("F{}", idx)).or_default().push(value);
+            }
+            let (a, b, c) = (values[0], values[1], values[2]);
+            target.push(3. * a + if b > 0.5 { 4. * c } else { -a } + 0.1 * rng.uniform());
+        }
+        (data, target)
+    }
+
+    fn check_additivity(tree: &Tree, data: &impl DataSet) {
+        let expected = tree.expected_value().unwrap();
+        let preds = tree.predict(data).unwrap();
+        let shap = tree.shap_values(data).unwrap();
+        assert_eq!(preds.len(), shap.len(), "Wrong number of rows");
+        let n_features = data.rows().unwrap().next().unwrap().unwrap().len();
+        for (pred, phi) in preds.iter().zip(shap) {
+            assert_eq!(n_features, phi.len(), "Wrong number of features");
+            let total = expected + phi.iter().sum::<f64>();
+            assert!(
+                (total - pred).abs() < 1e-9,
+                "{} does not sum to {}",
+                total,
+                pred
+            );
+        }
+    }
+
+    #[test]
+    fn test_single_split() {
+        let data = HashMap::from([
+            ("F1".to_string(), vec![1., 2., 3.]),
+            ("F2".to_string(), vec![0., 0., 0.]),
+        ]);
+        let target = vec![4., 1., 1.];
+        let config = TreeConfig {
+            max_depth: 2,
+            ..Default::default()
+        };
+        let tree = Tree::fit(&data, &target, &config, &SquaredError).unwrap();
+        assert_eq!(3., tree.cover, "Wrong root cover");
+        assert_eq!(2., tree.expected_value().unwrap(), "Wrong expected value");
+        let shap = tree.shap_values(&data).unwrap();
+        for (phi, expected) in shap.iter().zip([2., -1., -1.]) {
+            assert!((phi[0] - expected).abs() < 1e-12, "Wrong contribution");
+            assert_eq!(0., phi[1], "Unused feature should not contribute");
+        }
+    }
+    #[test]
+    fn test_shap_values_sum_to_predictions() {
+        let (data, target) = lcg_data(300);
+        for growth_policy in [GrowthPolicy::DepthWise, GrowthPolicy::LossGuide] {
+            let config = TreeConfig {
+                max_depth: 6,
+                max_leaf_nodes: Some(24),
+                growth_policy,
+                ..Default::default()
+            };
+            let tree = Tree::fit(&data, &target, &config, &SquaredError).unwrap();
+            assert_eq!(
+                3,
+                tree.feature_names().len(),
+                "Every feature should be used"
+            );
+            check_additivity(&tree, &data);
+        }
+
+        let weights = (0..target.len()).map(|row| 1. + (row % 3) as f64).collect();
+        let weighted = WeightedTarget::new(target.clone(), weights);
+        let config = TreeConfig {
+            max_depth: 4,
+            ..Default::default()
+        };
+        let tree = Tree::fit(&data, &weighted, &config, &SquaredError).unwrap();
+        assert_eq!(
+            weighted.weights().iter().sum::<f64>(),
+            tree.cover,
+            "Wrong cover"
+        );
+        check_additivity(&tree, &data);
+
+        let colors = [
+            "red", "blue", "green", "red", "blue", "green", "blue", "red",
+        ];
+        let data = MixedDataSet::new()
+            .with_numerical("F1", vec![1., f64::NAN, 3., 4., 5., f64::NAN, 7., 8.])
+            .with_categorical("color", CategoricalFeature::from_strings(&colors.map(Some)));
+        let target = vec![true, false, false, true, true, false, false, true];
+        let tree = Tree::fit(&data, &target, &config, &ScoringFunction::Gini(Gini)).unwrap();
+        check_additivity(&tree, &data);
+    }
+    #[test]
+    fn test_matches_brute_force() {
+        let (data, target) = lcg_data(200);
+        let config = TreeConfig {
+            max_depth: 5,
+            ..Default::default()
+        };
+        let tree = Tree::fit(&data, &target, &config, &SquaredError).unwrap();
+        let names = tree.feature_names();
+        let mut columns: Vec<&String> = data.keys().collect();
+        columns.sort();
+        let shap = tree.shap_values(&data).unwrap();
+        for (row, phi) in shap.iter().enumerate().take(40) {
+            let values: Vec<Option<f64>> = names
+                .iter()
+                .map(|name| Some(data[*name][row]).filter(|val| !val.is_nan()))
+                .collect();
+            for (name, exact) in names.iter().zip(brute_force_shap(&tree, &values)) {
+                let fast = phi[columns.iter().position(|column| column == name).unwrap()];
+                assert!((exact - fast).abs() < 1e-9, "{} is not {}", fast, exact);
+            }
+        }
+    }
+    #[test]
+    fn test_multi_value_leaves() {
+        let tree = Tree {
+            split_info: None,
+            left: None,
+            right: None,
+            prediction: Some(vec![0.2, 0.8]),
+            cover: 5.,
+        };
+        assert!(matches!(
+            tree.expected_value(),
+            Err(TreeError::MultiValueLeaf(2))
+        ));
+    }
+}
@@ -0,0 +1,293 @@
+use rayon::iter::IntoParallelRefIterator;
+use rayon::iter::ParallelIterator;
+
+use crate::tree::loss_fn::split_values::NullDirection;
+use crate::tree::loss_fn::split_values::SplitInfo;
+use crate::tree::loss_fn::Score;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::histogram::{FeatureHistogram, Histograms};
+use super::DataSet;
+use super::DataSetRowsError;
+use super::Row;
+use super::Splittable;
+use super::Target;
+
+const MAX_BINS: usize = u16::MAX as usize + 1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BinningError {
+    #[error("max_bins is {max_bins}, it must be between 1 and {max}")]
+    InvalidMaxBins { max_bins: usize, max: usize },
+}
+
+/// Feature quantized into bins: bin `i` holds the values in `[thresholds[i], thresholds[i + 1])`,
+/// NaN values are stored as null.
+#[derive(Debug, Clone)]
+pub struct BinnedFeature {
+    bins: Vec<Option<u16>>,
+    thresholds: Arc<[f64]>,
+}
+
+impl BinnedFeature {
+    fn new(values: &[f64], max_bins: usize) -> Self {
+        let mut sorted: Vec<f64> = values.iter().copied().filter(|v| !v.is_nan()).collect();
+        sorted.sort_by(f64::total_cmp);
+        let mut thresholds: Vec<f64> = (0..max_bins)
+            .filter_map(|bin| sorted.get(bin * sorted.len() / max_bins).copied())
+            .collect();
+        thresholds.dedup();
+        let bins = values
+            .iter()
+            .map(|v| match v.is_nan() {
+                true => None,
+                false => Some((thresholds.partition_point(|t| t <= v) - 1) as u16),
+            })
+            .collect();
+        BinnedFeature {
+            bins,
+            thresholds: thresholds.into(),
+        }
+    }
+    pub fn n_bins(&self) -> usize {
+        self.thresholds.len()
+    }
+    pub fn thresholds(&self) -> &[f64] {
+        &self.thresholds
+    }
+}
+
+/// Dataset whose features are quantized once into at most `max_bins` bins,
+/// splits are searched on bin boundaries using per-bin histograms of the target statistics.
+#[derive(Debug, Clone)]
+pub struct BinnedDataSet {
+    features: HashMap<String, BinnedFeature>,
+}
+
+impl BinnedDataSet {
+    pub fn new<F>(data: &HashMap<String, Vec<F>>, max_bins: usize) -> Result<Self, BinningError>
+    where
+        F: Into<f64> + Copy + Sync,
+    {
+        if !(1..=MAX_BINS).contains(&max_bins) {
+            return Err(BinningError::InvalidMaxBins {
+                max_bins,
+                max: MAX_BINS,
+            });
+        }
+        let features = data
+            .par_iter()
+            .map(|(name, values)| {
+                let values: Vec<f64> = values.iter().map(|v| (*v).into()).collect();
+                (name.clone(), BinnedFeature::new(&values, max_bins))
+            })
+            .collect();
+        Ok(BinnedDataSet { features })
+    }
+    pub fn feature(&self, name: &str) -> Option<&BinnedFeature> {
+        self.features.get(name)
+    }
+}
+
+impl Splittable for BinnedDataSet {
+    fn len(&self) -> usize {
+        self.features.values().map(|f| f.bins.len()).max().unwrap()
+    }
+    fn split(
+        &self,
+        mask: impl Iterator<Item = Option<bool>>,
+        null_direction: NullDirection,
+    ) -> (Self, Self) {
+        let mut left = HashMap::with_capacity(self.features.len());
+        let mut right = HashMap::with_capacity(self.features.len());
+
+        let mask: Vec<_> = mask.collect();
+
+        for (name, feature) in self.features.iter() {
+            let (left_bins, right_bins) = feature.bins.split(mask.iter().copied(), null_direction);
+            let thresholds = feature.thresholds.clone();
+            left.insert(
+                name.clone(),
+                BinnedFeature {
+                    bins: left_bins,
+                    thresholds: thresholds.clone(),
+                },
+            );
+            right.insert(
+                name.clone(),
+                BinnedFeature {
+                    bins: right_bins,
+                    thresholds,
+                },
+            );
+        }
+        (
+            BinnedDataSet { features: left },
+            BinnedDataSet { features: right },
+        )
+    }
+}
+
+impl DataSet for BinnedDataSet {
+    fn histograms<T, S: Score<T>>(
+        &self,
+        rows: &[usize],
+        target: &impl Target<T>,
+        score_function: &S,
+        parent: Option<&Histograms<S::Stats>>,
+    ) -> Histograms<S::Stats> {
+        self.features
+            .par_iter()
+            .map(|(name, feature)| {
+                // The parent may only keep some of the bins, which are then mapped to its own
+                let (thresholds, index): (Arc<[f64]>, Vec<usize>) =
+                    match parent.and_then(|p| p.get(name)) {
+                        Some(parent) => {
+                            let index = feature
+                                .thresholds
+                                .iter()
+                                .map(|t| parent.thresholds.partition_point(|p| p <= t).max(1) - 1)
+                                .collect();
+                            (parent.thresholds.clone(), index)
+                        }
+                        None => (feature.thresholds.clone(), (0..feature.n_bins()).collect()),
+                    };
+                let bins = rows.iter().map(|row| {
+                    (
+                        feature.bins[*row].map(|bin| index[bin as usize]),
+                        target.value(*row),
+                        target.weight(*row),
+                    )
+                });
+                let histogram = FeatureHistogram::build(thresholds, bins, score_function);
+                (name.clone(), histogram)
+            })
+            .collect()
+    }
+    fn split_mask(&self, split_info: &SplitInfo, rows: &[usize]) -> Vec<Option<bool>> {
+        let feature = &self.features[&split_info.name];
+        let boundary = feature
+            .thresholds
+            .partition_point(|t| *t < split_info.value);
+        rows.iter()
+            .map(|row| feature.bins[*row].map(|bin| (bin as usize) < boundary))
+            .collect()
+    }
+    fn num_rows(&self) -> Result<usize, DataSetRowsError> {
+        let max = self.features.values().map(|f| f.bins.len()).max();
+        match max {
+            Some(m) => Ok(m),
+            None => Err(DataSetRowsError::EmptyDF),
+        }
+    }
+    fn column(&self, name: &str) -> Option<Cow<'_, [f64]>> {
+        // Bins are represented by their lower threshold, as in `rows`
+        let feature = self.features.get(name)?;
+        let values = feature
+            .bins
+            .iter()
+            .map(|bin| bin.map_or(f64::NAN, |bin| feature.thresholds[bin as usize]))
+            .collect();
+        Some(Cow::Owned(values))
+    }
+    fn rows(
+        &self,
+    ) -> Result<
+        impl Iterator<Item = Result<Row<'_, impl Into<f64> + Copy>, DataSetRowsError>>,
+        DataSetRowsError,
+    > {
+        let indices = 0..self.num_rows()?;
+        // Bins are represented by their lower threshold, which routes them
+        // the same way as the raw values they hold
+        Ok(indices.into_iter().map(|idx| {
+            self.features
+                .iter()
+                .map(|(name, feature)| match feature.bins.get(idx) {
+                    Some(bin) => Ok((
+                        name.as_str(),
+                        bin.map(|bin| feature.thresholds[bin as usize]),
+                    )),
+                    None => Err(DataSetRowsError::IllFormedColumn(name.to_owned(), idx)),
+                })
+                .collect()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tree::loss_fn::{Gini, Logit, ScoringFunction};
+    use crate::tree::{Tree, TreeConfig};
+
+    #[test]
+    fn test_binning() {
+        let values: Vec<f64> = (0..100).map(|v| v as f64).chain([f64::NAN]).collect();
+        let feature = BinnedFeature::new(&values, 4);
+        assert_eq!(&[0., 25., 50., 75.], feature.thresholds(), "Wrong bins");
+        assert_eq!(Some(0), feature.bins[24], "Wrong bin for value");
+        assert_eq!(Some(1), feature.bins[25], "Wrong bin for threshold");
+        assert_eq!(Some(3), feature.bins[99], "Wrong bin for max");
+        assert_eq!(None, feature.bins[100], "NaN should be null");
+
+        let few_values = BinnedFeature::new(&[3., 1., 1., 2.], 256);
+        assert_eq!(&[1., 2., 3.], few_values.thresholds(), "Wrong bins");
+    }
+    #[test]
+    fn test_invalid_max_bins() {
+        let data = HashMap::from([("f1".to_owned(), vec![1., 2.])]);
+        for max_bins in [0, MAX_BINS + 1] {
+            assert!(matches!(
+                BinnedDataSet::new(&data, max_bins),
+                Err(BinningError::InvalidMaxBins { max: MAX_BINS, .. })
+            ));
+        }
+        assert!(BinnedDataSet::new(&data, MAX_BINS).is_ok());
+    }
+    #[test]
+    fn test_histogram_matches_exact_split() {
+        let data = HashMap::from([
+            ("f1".to_owned(), vec![1., 5., 3., 8., 2., 7., 4., 6.]),
+            ("f2".to_owned(), vec![2., 1., 4., 3., 6., 5., 8., 9.]),
+        ]);
+        let tar = vec![true, false, true, false, true, false, false, false];
+        let binned = BinnedDataSet::new(&data, 256).unwrap();
+        let tree_config = TreeConfig::default();
+        for score_fn in [
+            ScoringFunction::Gini(Gini),
+            ScoringFunction::Logit(Logit::new(0.5)),
+        ] {
+            let (exact, exact_mask) = data.find_best_split(&tar, &score_fn, &tree_config).unwrap();
+            let (hist, hist_mask) = binned
+                .find_best_split(&tar, &score_fn, &tree_config)
+                .unwrap();
+            assert_eq!(exact, hist, "Histogram split differs for {}", score_fn);
+            assert!(exact_mask.eq(hist_mask), "Wrong mask for {}", score_fn);
+        }
+    }
+    #[test]
+    fn test_binned_tree() {
+        let data = HashMap::from([(
+            "F1".to_string(),
+            (0..100).map(f64::from).collect::<Vec<_>>(),
+        )]);
+        let target: Vec<bool> = (0..100).map(|v| v < 37).collect();
+        let binned = BinnedDataSet::new(&data, 10).unwrap();
+        let tree_config = TreeConfig {
+            max_depth: 1,
+            ..Default::default()
+        };
+        let score_fn = ScoringFunction::Gini(Gini);
+        let tree = Tree::fit(&binned, &target, &tree_config, &score_fn).unwrap();
+        // 37 is not a bin boundary, the closest one is used
+        assert_eq!(40., tree.split_info.as_ref().unwrap().value, "Wrong split");
+        let preds = tree.predict(&data).unwrap();
+        assert_eq!(
+            preds,
+            tree.predict(&binned).unwrap(),
+            "Bins routed differently"
+        );
+    }
+}
@@ -0,0 +1,418 @@
+use rayon::iter::IntoParallelRefIterator;
+use rayon::iter::ParallelIterator;
+
+use crate::tree::loss_fn::split_values::NullDirection;
+use crate::tree::loss_fn::split_values::SplitInfo;
+use crate::tree::loss_fn::Score;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use super::histogram::{FeatureHistogram, FeatureKind, Histograms};
+use super::vector_datasets::{threshold_mask, value_histogram};
+use super::DataSet;
+use super::DataSetRowsError;
+use super::Row;
+use super::Splittable;
+use super::Target;
+
+/// Feature taking values in a set of unordered categories, stored as codes in
+/// `0..n_categories`. Missing values are stored as null.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CategoricalFeature {
+    codes: Vec<Option<u32>>,
+    categories: Vec<String>,
+}
+
+impl CategoricalFeature {
+    /// Feature from string categories, codes follow the order in which categories appear.
+    pub fn from_strings<S: AsRef<str>>(values: &[Option<S>]) -> Self {
+        CategoricalFeature::with_categories(values, &[])
+    }
+    /// Feature from string categories, reusing the codes of `categories` (e.g. the ones
+    /// of the training data). Categories that are not in `categories` get new codes,
+    /// which trees fitted on `categories` treat as unseen.
+    pub fn with_categories<S: AsRef<str>>(values: &[Option<S>], categories: &[String]) -> Self {
+        let mut categories = categories.to_vec();
+        let mut index: HashMap<String, u32> = categories
+            .iter()
+            .enumerate()
+            .map(|(code, category)| (category.clone(), code as u32))
+            .collect();
+        let codes = values
+            .iter()
+            .map(|value| {
+                value.as_ref().map(|value| {
+                    let value = value.as_ref();
+                    match index.get(value) {
+                        Some(code) => *code,
+                        None => {
+                            let code = categories.len() as u32;
+                            index.insert(value.to_owned(), code);
+                            categories.push(value.to_owned());
+                            code
+                        }
+                    }
+                })
+            })
+            .collect();
+        CategoricalFeature { codes, categories }
+    }
+    /// Feature from integer codes, which are expected to be dense: every code lower
+    /// than the largest one is a category.
+    pub fn from_codes(codes: Vec<Option<u32>>) -> Self {
+        let n_categories = codes.iter().flatten().max().map_or(0, |max| max + 1);
+        let categories = (0..n_categories).map(|code| code.to_string()).collect();
+        CategoricalFeature { codes, categories }
+    }
+    pub fn n_categories(&self) -> usize {
+        self.categories.len()
+    }
+    /// Names of the categories, indexed by code.
+    pub fn categories(&self) -> &[String] {
+        &self.categories
+    }
+    pub fn codes(&self) -> &[Option<u32>] {
+        &self.codes
+    }
+}
+
+impl Splittable for CategoricalFeature {
+    fn len(&self) -> usize {
+        self.codes.len()
+    }
+    fn split(
+        &self,
+        mask: impl Iterator<Item = Option<bool>>,
+        null_direction: NullDirection,
+    ) -> (Self, Self) {
+        let (left, right) = self.codes.split(mask, null_direction);
+        (
+            CategoricalFeature {
+                codes: left,
+                categories: self.categories.clone(),
+            },
+            CategoricalFeature {
+                codes: right,
+                categories: self.categories.clone(),
+            },
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+    Numerical(Vec<f64>),
+    Categorical(CategoricalFeature),
+    /// Low cardinality categorical feature, always split one category vs rest.
+    OneHot(CategoricalFeature),
+}
+
+impl Column {
+    fn len(&self) -> usize {
+        match self {
+            Column::Numerical(values) => values.len(),
+            Column::Categorical(feature) | Column::OneHot(feature) => feature.len(),
+        }
+    }
+    /// Value of a row as seen by `Tree::predict`, categories are represented by their code.
+    fn value(&self, row: usize) -> Option<Option<f64>> {
+        match self {
+            Column::Numerical(values) => values.get(row).map(|v| Some(*v)),
+            Column::Categorical(feature) | Column::OneHot(feature) => feature
+                .codes
+                .get(row)
+                .map(|code| code.map(|code| code as f64)),
+        }
+    }
+}
+
+/// Dataset mixing numerical and categorical features. Numerical features are split
+/// on thresholds, categorical ones on subsets of their categories.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MixedDataSet {
+    columns: HashMap<String, Column>,
+}
+
+impl MixedDataSet {
+    pub fn new() -> Self {
+        MixedDataSet::default()
+    }
+    pub fn with_numerical(mut self, name: &str, values: Vec<f64>) -> Self {
+        self.columns
+            .insert(name.to_owned(), Column::Numerical(values));
+        self
+    }
+    pub fn with_categorical(mut self, name: &str, feature: CategoricalFeature) -> Self {
+        self.columns
+            .insert(name.to_owned(), Column::Categorical(feature));
+        self
+    }
+    pub fn with_one_hot(mut self, name: &str, feature: CategoricalFeature) -> Self {
+        self.columns
+            .insert(name.to_owned(), Column::OneHot(feature));
+        self
+    }
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.get(name)
+    }
+}
+
+impl Splittable for MixedDataSet {
+    fn len(&self) -> usize {
+        self.columns.values().map(Column::len).max().unwrap()
+    }
+    fn split(
+        &self,
+        mask: impl Iterator<Item = Option<bool>>,
+        null_direction: NullDirection,
+    ) -> (Self, Self) {
+        let mut left = HashMap::with_capacity(self.columns.len());
+        let mut right = HashMap::with_capacity(self.columns.len());
+
+        let mask: Vec<_> = mask.collect();
+
+        for (name, column) in self.columns.iter() {
+            let (left_column, right_column) = match column {
+                Column::Numerical(values) => {
+                    let (l, r) = values.split(mask.iter().copied(), null_direction);
+                    (Column::Numerical(l), Column::Numerical(r))
+                }
+                Column::Categorical(feature) => {
+                    let (l, r) = feature.split(mask.iter().copied(), null_direction);
+                    (Column::Categorical(l), Column::Categorical(r))
+                }
+                Column::OneHot(feature) => {
+                    let (l, r) = feature.split(mask.iter().copied(), null_direction);
+                    (Column::OneHot(l), Column::OneHot(r))
+                }
+            };
+            left.insert(name.clone(), left_column);
+            right.insert(name.clone(), right_column);
+        }
+        (
+            MixedDataSet { columns: left },
+            MixedDataSet { columns: right },
+        )
+    }
+}
+
+impl DataSet for MixedDataSet {
+    fn histograms<T, S: Score<T>>(
+        &self,
+        rows: &[usize],
+        target: &impl Target<T>,
+        score_function: &S,
+        parent: Option<&Histograms<S::Stats>>,
+    ) -> Histograms<S::Stats> {
+        self.columns
+            .par_iter()
+            .map(|(name, column)| {
+                let histogram = match column {
+                    Column::Numerical(values) => {
+                        let parent = parent.and_then(|p| p.get(name));
+                        value_histogram(values, rows, target, score_function, parent)
+                    }
+                    Column::Categorical(feature) | Column::OneHot(feature) => {
+                        let kind = match column {
+                            Column::OneHot(_) => FeatureKind::OneHot,
+                            _ => FeatureKind::Categorical,
+                        };
+                        let codes = rows.iter().map(|row| {
+                            (
+                                feature.codes[*row].map(|code| code as usize),
+                                target.value(*row),
+                                target.weight(*row),
+                            )
+                        });
+                        FeatureHistogram::build_categorical(
+                            kind,
+                            feature.n_categories(),
+                            codes,
+                            score_function,
+                        )
+                    }
+                };
+                (name.clone(), histogram)
+            })
+            .collect()
+    }
+    fn split_mask(&self, split_info: &SplitInfo, rows: &[usize]) -> Vec<Option<bool>> {
+        match &self.columns[&split_info.name] {
+            Column::Numerical(values) => threshold_mask(values, split_info.value, rows),
+            Column::Categorical(feature) | Column::OneHot(feature) => rows
+                .iter()
+                .map(|row| feature.codes[*row].map(|code| split_info.goes_left(code as f64)))
+                .collect(),
+        }
+    }
+    fn num_rows(&self) -> Result<usize, DataSetRowsError> {
+        let max = self.columns.values().map(Column::len).max();
+        match max {
+            Some(m) => Ok(m),
+            None => Err(DataSetRowsError::EmptyDF),
+        }
+    }
+    fn column(&self, name: &str) -> Option<Cow<'_, [f64]>> {
+        match self.columns.get(name)? {
+            Column::Numerical(values) => Some(Cow::Borrowed(values)),
+            Column::Categorical(feature) | Column::OneHot(feature) => {
+                let codes = feature.codes.iter();
+                Some(Cow::Owned(
+                    codes
+                        .map(|code| code.map_or(f64::NAN, |code| code as f64))
+                        .collect(),
+                ))
+            }
+        }
+    }
+    fn rows(
+        &self,
+    ) -> Result<
+        impl Iterator<Item = Result<Row<'_, impl Into<f64> + Copy>, DataSetRowsError>>,
+        DataSetRowsError,
+    > {
+        let indices = 0..self.num_rows()?;
+        Ok(indices.into_iter().map(|idx| {
+            self.columns
+                .iter()
+                .map(|(name, column)| match column.value(idx) {
+                    Some(v) => Ok((name.as_str(), v)),
+                    None => Err(DataSetRowsError::IllFormedColumn(name.to_owned(), idx)),
+                })
+                .collect()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tree::loss_fn::split_values::{CategorySplit, SplitKind};
+    use crate::tree::loss_fn::{Gini, ScoringFunction, SquaredError};
+    use crate::tree::{Tree, TreeConfig};
+
+    #[test]
+    fn test_category_codes() {
+        let feature = CategoricalFeature::from_strings(&[Some("b"), None, Some("a"), Some("b")]);
+        assert_eq!(&[Some(0), None, Some(1), Some(0)], feature.codes());
+        assert_eq!(&["b".to_owned(), "a".to_owned()], feature.categories());
+
+        let test_feature =
+            CategoricalFeature::with_categories(&[Some("a"), Some("c")], feature.categories());
+        assert_eq!(&[Some(1), Some(2)], test_feature.codes(), "Wrong codes");
+        assert_eq!(3, test_feature.n_categories(), "Unseen category not added");
+    }
+    #[test]
+    fn test_optimal_partition() {
+        // Categories 0 and 2 have high targets, 1 and 3 low ones: no threshold on
+        // the codes can separate them
+        let codes = vec![0, 1, 2, 3, 0, 1, 2, 3, 2];
+        let data = MixedDataSet::new().with_categorical(
+            "f1",
+            CategoricalFeature::from_codes(codes.into_iter().map(Some).collect()),
+        );
+        let tar = vec![10., 1., 11., 2., 10., 1., 11., 2., 11.];
+        let tree_config = TreeConfig::default();
+        let (split, mask) = data
+            .find_best_split(&tar, &SquaredError, &tree_config)
+            .unwrap();
+        let expected = CategorySplit {
+            left: vec![1, 3],
+            right: vec![0, 2],
+            unseen_direction: NullDirection::Right,
+        };
+        assert_eq!(
+            SplitKind::Categories(expected),
+            split.kind,
+            "Wrong partition"
+        );
+        // {1, 2, 1, 2} vs {10, 11, 10, 11, 11}
+        assert!(
+            (split.score.score - (1. + 1.2)).abs() < 1e-12,
+            "Wrong score"
+        );
+        let mask: Vec<_> = mask.collect();
+        assert_eq!(Some(false), mask[0], "Wrong mask");
+        assert_eq!(Some(true), mask[3], "Wrong mask");
+    }
+    #[test]
+    fn test_one_hot_split() {
+        let codes: Vec<Option<u32>> = [0, 1, 2, 3, 0, 1, 2, 3, 2].into_iter().map(Some).collect();
+        let feature = CategoricalFeature::from_codes(codes);
+        let tar = vec![10., 1., 11., 2., 10., 1., 11., 2., 11.];
+        let low_cardinality_config = TreeConfig {
+            max_cat_to_onehot: 4,
+            ..Default::default()
+        };
+        let data = MixedDataSet::new().with_categorical("f1", feature.clone());
+        let one_hot_data = MixedDataSet::new().with_one_hot("f1", feature);
+        for (data, tree_config) in [
+            (&data, &low_cardinality_config),
+            (&one_hot_data, &TreeConfig::default()),
+        ] {
+            let (split, _) = data
+                .find_best_split(&tar, &SquaredError, tree_config)
+                .unwrap();
+            assert_eq!(SplitKind::Equality, split.kind, "Wrong split kind");
+            assert_eq!(2., split.value, "Wrong isolated category");
+            assert!(split.goes_left(2.), "Isolated category should go left");
+            assert!(!split.goes_left(7.), "Unseen category should go right");
+        }
+
+        // Only category 2 has enough rows to form a group on its own
+        let min_group_config = TreeConfig {
+            min_data_per_group: 3,
+            ..Default::default()
+        };
+        let (split, _) = data
+            .find_best_split(&tar, &SquaredError, &min_group_config)
+            .unwrap();
+        let expected = CategorySplit {
+            left: vec![2],
+            right: vec![0, 1, 3],
+            unseen_direction: NullDirection::Right,
+        };
+        assert_eq!(SplitKind::Categories(expected), split.kind, "Wrong groups");
+    }
+    #[test]
+    fn test_categorical_tree() {
+        let train_colors = [Some("red"), Some("blue"), Some("green"), None];
+        let colors = CategoricalFeature::from_strings(&train_colors.repeat(2));
+        let data = MixedDataSet::new()
+            .with_categorical("color", colors.clone())
+            .with_numerical("size", vec![1., 2., 3., 4., 5., 6., 7., 8.]);
+        let target = vec![true, false, true, false, true, false, true, false];
+        let tree_config = TreeConfig {
+            max_depth: 2,
+            ..Default::default()
+        };
+        let score_fn = ScoringFunction::Gini(Gini);
+        let tree = Tree::fit(&data, &target, &tree_config, &score_fn).unwrap();
+        let split_info = tree.split_info.as_ref().unwrap();
+        assert_eq!("color", split_info.name, "Wrong split feature");
+        assert_eq!(
+            target.iter().map(|t| t as u8 as f64).collect::<Vec<_>>(),
+            tree.predict(&data).unwrap(),
+            "Wrong predictions"
+        );
+
+        // "yellow" was never seen while fitting
+        let test_colors = CategoricalFeature::with_categories(
+            &[Some("yellow"), Some("green")],
+            colors.categories(),
+        );
+        let test_data = MixedDataSet::new()
+            .with_categorical("color", test_colors)
+            .with_numerical("size", vec![1., 2.]);
+        let SplitKind::Categories(categories) = &split_info.kind else {
+            panic!("Color should be split on a partition")
+        };
+        let unseen_pred = match categories.unseen_direction {
+            NullDirection::Left => tree.left.as_ref().unwrap().prediction.as_ref(),
+            NullDirection::Right => tree.right.as_ref().unwrap().prediction.as_ref(),
+        };
+        let preds = tree.predict(&test_data).unwrap();
+        assert_eq!(unseen_pred.unwrap()[0], preds[0], "Wrong unseen routing");
+        assert_eq!(1., preds[1], "Wrong seen routing");
+    }
+}
@@ -0,0 +1,446 @@
+use rayon::iter::IndexedParallelIterator;
+use rayon::iter::IntoParallelRefIterator;
+use rayon::iter::IntoParallelRefMutIterator;
+use rayon::iter::ParallelIterator;
+
+use crate::tree::loss_fn::split_stats::SplitStats;
+use crate::tree::loss_fn::split_values::{CategorySplit, NullDirection, SplitInfo, SplitScore};
+use crate::tree::loss_fn::Score;
+use crate::tree::TreeConfig;
+use core::cmp::Ordering;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use super::satisfies_leaf_constraints;
+use super::BestSplitNotFound;
+use super::SplitStrategy;
+
+/// Target statistics of a feature aggregated per split candidate: `bins[i]` holds the rows
+/// with values in `[thresholds[i], thresholds[i + 1])`, `null` the rows with missing values.
+/// Categorical features have one bin per category code instead.
+#[derive(Debug, Clone)]
+pub struct FeatureHistogram<St> {
+    pub thresholds: Arc<[f64]>,
+    pub bins: Vec<St>,
+    pub null: St,
+    pub kind: FeatureKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureKind {
+    /// Split on thresholds.
+    Numerical,
+    /// Split on partitions of the categories, or one category vs rest when the feature
+    /// has at most `TreeConfig::max_cat_to_onehot` categories.
+    Categorical,
+    /// Always split one category vs rest.
+    OneHot,
+}
+
+pub type Histograms<St> = HashMap<String, FeatureHistogram<St>>;
+
+/// Keeps the best of the scored candidates, remembering why none was kept otherwise.
+struct BestSplit<'a, T, S: Score<T>> {
+    best: Result<SplitInfo, BestSplitNotFound>,
+    score_function: &'a S,
+    tree_config: &'a TreeConfig,
+    target_type: PhantomData<T>,
+}
+
+impl<'a, T, S: Score<T>> BestSplit<'a, T, S> {
+    fn new(score_function: &'a S, tree_config: &'a TreeConfig) -> Self {
+        BestSplit {
+            best: Err(BestSplitNotFound::NoSplitRequired),
+            score_function,
+            tree_config,
+            target_type: PhantomData,
+        }
+    }
+    fn consider(
+        &mut self,
+        left: &S::Stats,
+        right: &S::Stats,
+        null: &S::Stats,
+        split_info: impl FnOnce(SplitScore) -> SplitInfo,
+    ) {
+        match self.score_function.stats_score(left, right, null) {
+            Ok(score) => {
+                if !satisfies_leaf_constraints(
+                    left,
+                    right,
+                    null,
+                    score.null_direction,
+                    self.score_function,
+                    self.tree_config,
+                ) {
+                    if self.best.is_err() {
+                        self.best = Err(BestSplitNotFound::ConstraintsNotMet);
+                    }
+                } else if self
+                    .best
+                    .as_ref()
+                    .map_or(true, |best_split| score.score < best_split.score.score)
+                {
+                    self.best = Ok(split_info(score));
+                }
+            }
+            Err(err) => {
+                if self.best.is_err() {
+                    self.best = Err(err.into());
+                }
+            }
+        }
+    }
+}
+
+impl<St: SplitStats> FeatureHistogram<St> {
+    /// Accumulates `(bin, value, weight)` rows, a `None` bin is a missing value.
+    pub fn build<T, S: Score<T, Stats = St>>(
+        thresholds: Arc<[f64]>,
+        rows: impl Iterator<Item = (Option<usize>, T, f64)>,
+        score_function: &S,
+    ) -> Self {
+        let mut bins = vec![score_function.empty_stats(); thresholds.len()];
+        let mut null = score_function.empty_stats();
+        for (bin, val, weight) in rows {
+            match bin {
+                Some(bin) => score_function.update_stats(&mut bins[bin], val, weight),
+                None => score_function.update_stats(&mut null, val, weight),
+            }
+        }
+        FeatureHistogram {
+            thresholds,
+            bins,
+            null,
+            kind: FeatureKind::Numerical,
+        }
+    }
+    /// Accumulates `(category, value, weight)` rows of a feature with `n_categories`
+    /// categories, a `None` category is a missing value.
+    pub fn build_categorical<T, S: Score<T, Stats = St>>(
+        kind: FeatureKind,
+        n_categories: usize,
+        rows: impl Iterator<Item = (Option<usize>, T, f64)>,
+        score_function: &S,
+    ) -> Self {
+        let codes: Arc<[f64]> = (0..n_categories).map(|code| code as f64).collect();
+        FeatureHistogram {
+            kind,
+            ..FeatureHistogram::build(codes, rows, score_function)
+        }
+    }
+    pub fn subtract(&mut self, other: &Self) {
+        for (bin, other_bin) in self.bins.iter_mut().zip(other.bins.iter()) {
+            bin.subtract(other_bin);
+        }
+        self.null.subtract(&other.null);
+    }
+    /// Drops the empty bins of a numerical feature, merging their ranges into the previous
+    /// bin: a node only keeps the candidates of its own rows, not the ones of the root.
+    /// Category codes index the bins of categorical features, which are left untouched.
+    pub fn compact(&mut self) {
+        if self.kind != FeatureKind::Numerical || self.bins.iter().all(|bin| bin.count() > 0) {
+            return;
+        }
+        let (thresholds, bins): (Vec<f64>, Vec<St>) = self
+            .thresholds
+            .iter()
+            .zip(self.bins.drain(..))
+            .filter(|(_, bin)| bin.count() > 0)
+            .unzip();
+        self.thresholds = thresholds.into();
+        self.bins = bins;
+    }
+    /// `seed` draws the threshold of `SplitStrategy::Random` splits.
+    fn best_split<T, S: Score<T, Stats = St>>(
+        &self,
+        name: &str,
+        score_function: &S,
+        tree_config: &TreeConfig,
+        seed: u64,
+    ) -> Result<SplitInfo, BestSplitNotFound> {
+        let one_hot = self.bins.len() <= tree_config.max_cat_to_onehot;
+        match self.kind {
+            FeatureKind::Numerical => match tree_config.split_strategy {
+                SplitStrategy::Best => self.best_threshold_split(name, score_function, tree_config),
+                SplitStrategy::Random => {
+                    let mut rng = StdRng::seed_from_u64(seed);
+                    self.random_threshold_split(name, score_function, tree_config, &mut rng)
+                }
+            },
+            FeatureKind::Categorical if !one_hot => {
+                self.best_category_split(name, score_function, tree_config)
+            }
+            FeatureKind::Categorical | FeatureKind::OneHot => {
+                self.best_one_hot_split(name, score_function, tree_config)
+            }
+        }
+    }
+    /// Scans the candidates present in the node: rows with values lower than the
+    /// threshold of a non empty bin go left.
+    fn best_threshold_split<T, S: Score<T, Stats = St>>(
+        &self,
+        name: &str,
+        score_function: &S,
+        tree_config: &TreeConfig,
+    ) -> Result<SplitInfo, BestSplitNotFound> {
+        let mut total = score_function.empty_stats();
+        self.bins.iter().for_each(|bin| total.merge(bin));
+
+        let mut left = score_function.empty_stats();
+        let mut right = total.clone();
+        let mut best = BestSplit::new(score_function, tree_config);
+        for (bin, threshold) in self.bins.iter().zip(self.thresholds.iter()) {
+            if bin.count() == 0 {
+                continue;
+            }
+            best.consider(&left, &right, &self.null, |score| {
+                SplitInfo::new(name.to_owned(), *threshold, score)
+            });
+            left.merge(bin);
+            right.subtract(bin);
+        }
+        best.best
+    }
+    /// Scores the split on a threshold drawn uniformly between the smallest and the largest
+    /// value of the node (Extremely Randomized Trees). The threshold is moved up to the
+    /// closest candidate, which routes the rows of the node the same way.
+    fn random_threshold_split<T, S: Score<T, Stats = St>>(
+        &self,
+        name: &str,
+        score_function: &S,
+        tree_config: &TreeConfig,
+        rng: &mut StdRng,
+    ) -> Result<SplitInfo, BestSplitNotFound> {
+        let present: Vec<usize> = (0..self.bins.len())
+            .filter(|bin| self.bins[*bin].count() > 0)
+            .collect();
+        let (Some(first), Some(last)) = (present.first(), present.last()) else {
+            return Err(BestSplitNotFound::NoSplitRequired);
+        };
+        if first == last {
+            return Err(BestSplitNotFound::NoSplitRequired);
+        }
+        let (min, max) = (self.thresholds[*first], self.thresholds[*last]);
+        // In (min, max]: the left child is never empty
+        let drawn = max - rng.gen::<f64>() * (max - min);
+        let boundary = present
+            .iter()
+            .find(|bin| self.thresholds[**bin] >= drawn)
+            .copied()
+            .unwrap_or(*last);
+
+        let mut left = score_function.empty_stats();
+        let mut right = score_function.empty_stats();
+        for (bin, stats) in self.bins.iter().enumerate() {
+            match bin < boundary {
+                true => left.merge(stats),
+                false => right.merge(stats),
+            }
+        }
+        let mut best = BestSplit::new(score_function, tree_config);
+        best.consider(&left, &right, &self.null, |score| {
+            SplitInfo::new(name.to_owned(), self.thresholds[boundary], score)
+        });
+        best.best
+    }
+    /// Sorts the categories present in the node by `SplitStats::category_key` and scans
+    /// the partitions of the sorted categories (Fisher's method), which contain the optimal
+    /// one for binary and regression targets. Categories with less than `min_data_per_group`
+    /// rows always go right, categories absent from the node go to the heaviest child.
+    fn best_category_split<T, S: Score<T, Stats = St>>(
+        &self,
+        name: &str,
+        score_function: &S,
+        tree_config: &TreeConfig,
+    ) -> Result<SplitInfo, BestSplitNotFound> {
+        let min_data_per_group = tree_config.min_data_per_group.max(1);
+        let (mut categories, rare): (Vec<u32>, Vec<u32>) = (0..self.bins.len() as u32)
+            .filter(|code| self.bins[*code as usize].count() > 0)
+            .partition(|code| self.bins[*code as usize].count() >= min_data_per_group);
+        categories.sort_by(|a, b| {
+            let (a, b) = (&self.bins[*a as usize], &self.bins[*b as usize]);
+            a.category_key().total_cmp(&b.category_key())
+        });
+        let mut total = score_function.empty_stats();
+        self.bins.iter().for_each(|bin| total.merge(bin));
+
+        let mut left = score_function.empty_stats();
+        let mut right = total.clone();
+        let mut best = BestSplit::new(score_function, tree_config);
+        let max_left = match rare.is_empty() {
+            true => categories.len().saturating_sub(1),
+            false => categories.len(),
+        };
+        for n_left in 1..=max_left {
+            let bin = &self.bins[categories[n_left - 1] as usize];
+            left.merge(bin);
+            right.subtract(bin);
+            if right.count() < min_data_per_group {
+                continue;
+            }
+            best.consider(&left, &right, &self.null, |score| {
+                let unseen_direction = match left.weight() >= right.weight() {
+                    true => NullDirection::Left,
+                    false => NullDirection::Right,
+                };
+                let mut left_codes = categories[..n_left].to_vec();
+                let mut right_codes = categories[n_left..].to_vec();
+                right_codes.extend_from_slice(&rare);
+                left_codes.sort();
+                right_codes.sort();
+                let categories = CategorySplit {
+                    left: left_codes,
+                    right: right_codes,
+                    unseen_direction,
+                };
+                SplitInfo::categorical(name.to_owned(), categories, score)
+            });
+        }
+        best.best
+    }
+    /// Scans the splits isolating one category present in the node from the rest,
+    /// categories with less than `min_data_per_group` rows are never isolated.
+    fn best_one_hot_split<T, S: Score<T, Stats = St>>(
+        &self,
+        name: &str,
+        score_function: &S,
+        tree_config: &TreeConfig,
+    ) -> Result<SplitInfo, BestSplitNotFound> {
+        let min_data_per_group = tree_config.min_data_per_group.max(1);
+        let mut total = score_function.empty_stats();
+        self.bins.iter().for_each(|bin| total.merge(bin));
+
+        // The category is moved to `left` and back, the stats are never copied
+        let mut left = score_function.empty_stats();
+        let mut rest = total.clone();
+        let mut best = BestSplit::new(score_function, tree_config);
+        for (code, bin) in self.bins.iter().enumerate() {
+            if bin.count() < min_data_per_group || total.count() - bin.count() < min_data_per_group
+            {
+                continue;
+            }
+            left.merge(bin);
+            rest.subtract(bin);
+            best.consider(&left, &rest, &self.null, |score| {
+                SplitInfo::equality(name.to_owned(), code as u32, score)
+            });
+            left.subtract(bin);
+            rest.merge(bin);
+        }
+        best.best
+    }
+}
+
+/// Subtracts the histograms of a child from its parent ones, yielding its sibling histograms.
+pub fn subtract_histograms<St: SplitStats>(parent: &mut Histograms<St>, child: &Histograms<St>) {
+    parent.par_iter_mut().for_each(|(name, histogram)| {
+        if let Some(child_histogram) = child.get(name) {
+            histogram.subtract(child_histogram);
+        }
+    });
+}
+
+/// Drops the empty bins of the numerical features, see `FeatureHistogram::compact`.
+pub fn compact_histograms<St: SplitStats>(histograms: &mut Histograms<St>) {
+    histograms
+        .par_iter_mut()
+        .for_each(|(_, histogram)| histogram.compact());
+}
+
+/// Best split over all features, ties are broken by feature name to keep fits reproducible.
+pub fn best_histogram_split<T, S: Score<T>>(
+    histograms: &Histograms<S::Stats>,
+    score_function: &S,
+    tree_config: &TreeConfig,
+) -> Result<SplitInfo, BestSplitNotFound> {
+    let mut features: Vec<&String> = histograms.keys().collect();
+    features.sort();
+    best_split_among(
+        histograms,
+        &features,
+        score_function,
+        tree_config,
+        tree_config.seed,
+    )
+}
+
+/// Best split over the given features only, e.g. the ones sampled for a node. The random
+/// thresholds of a feature are drawn from `seed` and its position in `features`.
+pub fn best_split_among<T, S: Score<T>>(
+    histograms: &Histograms<S::Stats>,
+    features: &[&String],
+    score_function: &S,
+    tree_config: &TreeConfig,
+    seed: u64,
+) -> Result<SplitInfo, BestSplitNotFound> {
+    let min_sp = |s1: SplitInfo, s2: SplitInfo| match s1.partial_cmp(&s2) {
+        Some(Ordering::Less) => Ok(s1),
+        Some(Ordering::Equal) if s1.name <= s2.name => Ok(s1),
+        Some(Ordering::Equal) | Some(Ordering::Greater) => Ok(s2),
+        None => Err(BestSplitNotFound::ScoreNotComparable(Box::new((s1, s2)))),
+    };
+    features
+        .par_iter()
+        .enumerate()
+        .filter_map(|(idx, name)| {
+            histograms
+                .get(*name)
+                .map(|histogram| (idx, name, histogram))
+        })
+        .map(|(idx, name, histogram)| {
+            let feature_seed = seed.wrapping_add(idx as u64);
+            histogram.best_split(name, score_function, tree_config, feature_seed)
+        })
+        .reduce(
+            || Err(BestSplitNotFound::NoSplitRequired),
+            |acc, el| match (acc, el) {
+                (Ok(acc), Ok(el)) => min_sp(acc, el),
+                (Ok(acc), Err(_)) => Ok(acc),
+                (Err(_), Ok(el)) => Ok(el),
+                (Err(acc), Err(_)) => Err(acc),
+            },
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tree::loss_fn::Gini;
+    use crate::tree::split::DataSet;
+
+    #[test]
+    fn test_sibling_from_subtraction() {
+        let parent_data = HashMap::from([("f1".to_owned(), vec![1., 2., 2., 3., f64::NAN, 4.])]);
+        let parent_tar = vec![true, false, true, false, true, true];
+        let child_data = HashMap::from([("f1".to_owned(), vec![2., f64::NAN, 4.])]);
+        let child_tar = vec![false, true, true];
+        let sibling_data = HashMap::from([("f1".to_owned(), vec![1., 2., 3.])]);
+        let sibling_tar = vec![true, true, false];
+
+        let parent = parent_data.histograms(&[0, 1, 2, 3, 4, 5], &parent_tar, &Gini, None);
+        let child = child_data.histograms(&[0, 1, 2], &child_tar, &Gini, Some(&parent));
+        let sibling = sibling_data.histograms(&[0, 1, 2], &sibling_tar, &Gini, Some(&parent));
+        let mut derived = parent.clone();
+        subtract_histograms(&mut derived, &child);
+
+        assert_eq!(
+            &[1., 2., 3., 4.],
+            &*derived["f1"].thresholds,
+            "Wrong candidates"
+        );
+        assert_eq!(sibling["f1"].bins, derived["f1"].bins, "Wrong bins");
+        assert_eq!(sibling["f1"].null, derived["f1"].null, "Wrong null bin");
+
+        compact_histograms(&mut derived);
+        assert_eq!(&[1., 2., 3.], &*derived["f1"].thresholds, "Not compacted");
+        let compacted = sibling_data.histograms(&[0, 1, 2], &sibling_tar, &Gini, Some(&derived));
+        assert_eq!(
+            compacted["f1"].bins, derived["f1"].bins,
+            "Wrong compacted bins"
+        );
+    }
+}
@@ -1,18 +1,39 @@
+pub mod binned;
+pub mod categorical;
+pub mod histogram;
 pub mod vector_datasets;
 
 use super::loss_fn::{
+    split_stats::SplitStats,
     split_values::{NullDirection, SplitInfo},
     Score, ScoreError,
 };
+use super::TreeConfig;
+use histogram::{best_histogram_split, Histograms};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// How the threshold of a numerical feature is chosen when searching the split of a node.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SplitStrategy {
+    /// Scan every candidate threshold.
+    #[default]
+    Best,
+    /// Score a single threshold drawn uniformly between the min and max of the feature.
+    /// Categorical features are still searched for their best split.
+    Random,
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum BestSplitNotFound {
     #[error("Split not found: {0}")]
     Score(#[from] ScoreError),
     #[error("Split not found: cannot compare score {} and {}", 0.0, 0.1)]
-    ScoreNotComparable((SplitInfo, SplitInfo)),
+    ScoreNotComparable(Box<(SplitInfo, SplitInfo)>),
     #[error("Split not found: split not needed")]
     NoSplitRequired,
+    #[error("Split not found: no candidate satisfies the tree constraints")]
+    ConstraintsNotMet,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -25,6 +46,9 @@ pub enum DataSetRowsError {
 
 pub trait Splittable: Sized {
     fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
     fn split(
         &self,
         mask: impl Iterator<Item = Option<bool>>,
@@ -37,21 +61,313 @@ pub trait Feature<T: PartialOrd>: Splittable {
     fn mask<'a>(&'a self, split: T) -> impl Iterator<Item = Option<bool>> + 'a + Clone;
 }
 
-pub trait Target<T>: Splittable + Sync {
+pub trait Target<T>: Sync {
     fn iter(&self) -> impl Iterator<Item = T>;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn value(&self, row: usize) -> T;
+    /// Weight of a row, rows are unweighted unless the target says otherwise.
+    fn weight(&self, _row: usize) -> f64 {
+        1.
+    }
+    /// Values along with their weights.
+    fn iter_weighted(&self) -> impl Iterator<Item = (T, f64)> {
+        (0..self.len()).map(|row| (self.value(row), self.weight(row)))
+    }
+}
+
+/// Rows of a shared target that belong to a node.
+pub struct TargetRows<'a, Tg> {
+    target: &'a Tg,
+    rows: &'a [usize],
+}
+
+impl<'a, Tg> TargetRows<'a, Tg> {
+    pub fn new(target: &'a Tg, rows: &'a [usize]) -> Self {
+        TargetRows { target, rows }
+    }
+}
+
+impl<T, Tg: Target<T>> Target<T> for TargetRows<'_, Tg> {
+    fn iter(&self) -> impl Iterator<Item = T> {
+        self.rows.iter().map(|row| self.target.value(*row))
+    }
+    fn len(&self) -> usize {
+        self.rows.len()
+    }
+    fn value(&self, row: usize) -> T {
+        self.target.value(self.rows[row])
+    }
+    fn weight(&self, row: usize) -> f64 {
+        self.target.weight(self.rows[row])
+    }
+}
+
+/// Checks `valid` on every value, `expected` describes the valid values. NaN values are
+/// never valid.
+pub fn check_target_values(
+    mut values: impl Iterator<Item = f64>,
+    valid: impl Fn(f64) -> bool,
+    expected: &'static str,
+) -> Result<(), ScoreError> {
+    match values.find(|value| value.is_nan() || !valid(*value)) {
+        Some(value) => Err(ScoreError::InvalidTargetValue { value, expected }),
+        None => Ok(()),
+    }
+}
+
+/// Continuous target whose values are all non negative, e.g. counts or amounts fitted
+/// with log-link objectives.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NonNegativeTarget {
+    values: Vec<f64>,
+}
+
+impl NonNegativeTarget {
+    pub fn new(values: Vec<f64>) -> Result<Self, ScoreError> {
+        check_target_values(values.as_slice().iter().copied(), |v| v >= 0., "gte than 0")?;
+        Ok(NonNegativeTarget { values })
+    }
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+}
+
+impl Target<f64> for NonNegativeTarget {
+    fn iter(&self) -> impl Iterator<Item = f64> {
+        self.values.as_slice().iter().copied()
+    }
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+    fn value(&self, row: usize) -> f64 {
+        self.values[row]
+    }
+}
+
+/// Target whose rows carry a weight, e.g. to rebalance classes or for importance sampling.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeightedTarget<Tg> {
+    target: Tg,
+    weights: Vec<f64>,
+}
+
+impl<Tg> WeightedTarget<Tg> {
+    pub fn new<T>(target: Tg, weights: Vec<f64>) -> Self
+    where
+        Tg: Target<T>,
+    {
+        if target.len() != weights.len() {
+            panic!(
+                "Got {} weights for a target of len {}",
+                weights.len(),
+                target.len()
+            );
+        }
+        if weights.as_slice().iter().any(|w| w.is_nan() || *w < 0.) {
+            panic!("Weights must be non negative");
+        }
+        WeightedTarget { target, weights }
+    }
+    /// Weights every row by `n_rows / (n_classes * class_count)`, so that
+    /// each class has the same total weight.
+    pub fn balanced<T: Into<u64>>(target: Tg) -> Self
+    where
+        Tg: Target<T>,
+    {
+        let mut class_counts: HashMap<u64, usize> = HashMap::new();
+        let classes: Vec<u64> = target.iter().map(Into::into).collect();
+        for class in classes.iter() {
+            *class_counts.entry(*class).or_default() += 1;
+        }
+        let n_rows = classes.len() as f64;
+        let n_classes = class_counts.len() as f64;
+        let weights = classes
+            .iter()
+            .map(|class| n_rows / (n_classes * class_counts[class] as f64))
+            .collect();
+        WeightedTarget::new(target, weights)
+    }
+    pub fn target(&self) -> &Tg {
+        &self.target
+    }
+    pub fn weights(&self) -> &[f64] {
+        &self.weights
+    }
 }
 
-pub trait DataSet: Splittable {
+impl<T, Tg: Target<T>> Target<T> for WeightedTarget<Tg> {
+    fn iter(&self) -> impl Iterator<Item = T> {
+        self.target.iter()
+    }
+    fn len(&self) -> usize {
+        self.weights.len()
+    }
+    fn value(&self, row: usize) -> T {
+        self.target.value(row)
+    }
+    fn weight(&self, row: usize) -> f64 {
+        self.weights[row]
+    }
+}
+
+impl<Tg: Splittable> Splittable for WeightedTarget<Tg> {
+    fn len(&self) -> usize {
+        self.weights.len()
+    }
+    fn split(
+        &self,
+        mask: impl Iterator<Item = Option<bool>>,
+        null_direction: NullDirection,
+    ) -> (Self, Self) {
+        let mask: Vec<_> = mask.collect();
+        let (left_target, right_target) = self.target.split(mask.iter().copied(), null_direction);
+        let (left_weights, right_weights) =
+            self.weights.split(mask.iter().copied(), null_direction);
+        (
+            WeightedTarget {
+                target: left_target,
+                weights: left_weights,
+            },
+            WeightedTarget {
+                target: right_target,
+                weights: right_weights,
+            },
+        )
+    }
+}
+
+/// Partitions `rows` in place so that the rows going left come first,
+/// `mask` is aligned with `rows`. Returns the number of rows going left.
+pub fn partition_rows(
+    rows: &mut [usize],
+    mask: &[Option<bool>],
+    null_direction: NullDirection,
+) -> usize {
+    let null_left = matches!(null_direction, NullDirection::Left);
+    let mut goes_left: Vec<bool> = mask.iter().map(|m| m.unwrap_or(null_left)).collect();
+    let (mut left_end, mut right_start) = (0, rows.len());
+    while left_end < right_start {
+        if goes_left[left_end] {
+            left_end += 1;
+        } else {
+            right_start -= 1;
+            rows.swap(left_end, right_start);
+            goes_left.swap(left_end, right_start);
+        }
+    }
+    left_end
+}
+
+pub type Row<'a, V> = Vec<(&'a str, Option<V>)>;
+
+pub trait DataSet: Splittable + Sync {
+    /// Histograms of the target statistics of `rows` for every feature, `target` is indexed
+    /// like the dataset. When the histograms of the parent node are given, the child ones
+    /// are built on the same split candidates so that they can be subtracted from each other.
+    fn histograms<T, S: Score<T>>(
+        &self,
+        rows: &[usize],
+        target: &impl Target<T>,
+        score_function: &S,
+        parent: Option<&Histograms<S::Stats>>,
+    ) -> Histograms<S::Stats>;
+    /// Routing of `rows` for the split: `Some(true)` goes left, `None` is a missing value.
+    fn split_mask(&self, split_info: &SplitInfo, rows: &[usize]) -> Vec<Option<bool>>;
     fn find_best_split<T, S: Score<T>>(
         &self,
         target: &impl Target<T>,
         score_function: &S,
-    ) -> Result<(SplitInfo, impl Iterator<Item = Option<bool>> + Clone), BestSplitNotFound>;
+        tree_config: &TreeConfig,
+    ) -> Result<(SplitInfo, impl Iterator<Item = Option<bool>> + Clone), BestSplitNotFound> {
+        let rows: Vec<usize> = (0..target.len()).collect();
+        let histograms = self.histograms(&rows, target, score_function, None);
+        let split_info = best_histogram_split(&histograms, score_function, tree_config)?;
+        let mask = self.split_mask(&split_info, &rows);
+        Ok((split_info, mask.into_iter()))
+    }
     fn num_rows(&self) -> Result<usize, DataSetRowsError>;
+    /// Values of a feature as routed by `Tree::predict` (category codes for categorical
+    /// features), NaN when missing. `None` if the feature is not in the dataset.
+    fn column(&self, name: &str) -> Option<Cow<'_, [f64]>>;
     fn rows(
         &self,
     ) -> Result<
-        impl Iterator<Item = Result<Vec<(&str, Option<impl Into<f64> + Copy>)>, DataSetRowsError>>,
+        impl Iterator<Item = Result<Row<'_, impl Into<f64> + Copy>, DataSetRowsError>>,
         DataSetRowsError,
     >;
 }
+
+/// Checks that both children of a candidate split respect `min_samples_leaf`
+/// and `min_child_weight`. Null rows are counted on the `null_direction` side.
+pub fn satisfies_leaf_constraints<T, S: Score<T>>(
+    left: &S::Stats,
+    right: &S::Stats,
+    null: &S::Stats,
+    null_direction: NullDirection,
+    score_function: &S,
+    tree_config: &TreeConfig,
+) -> bool {
+    // Counts and hessians add up: the null rows are not merged into a copy of a child
+    let (left_nulls, right_nulls) = match null_direction {
+        NullDirection::Left => (Some(null), None),
+        NullDirection::Right => (None, Some(null)),
+    };
+    [(left, left_nulls), (right, right_nulls)]
+        .iter()
+        .all(|(child, nulls)| {
+            let count = child.count() + nulls.map_or(0, |null| null.count());
+            let hessian = score_function.stats_hessian(child)
+                + nulls.map_or(0., |null| score_function.stats_hessian(null));
+            count >= tree_config.min_samples_leaf && hessian >= tree_config.min_child_weight
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_partition_rows() {
+        let mut rows = vec![0, 1, 2, 3, 4, 5];
+        let mask = vec![Some(false), Some(true), None, Some(true), Some(false), None];
+        let n_left = partition_rows(&mut rows, &mask, NullDirection::Right);
+        assert_eq!(2, n_left, "Wrong number of left rows");
+        let (mut left, mut right) = (rows[..n_left].to_vec(), rows[n_left..].to_vec());
+        left.sort();
+        right.sort();
+        assert_eq!(vec![1, 3], left, "Wrong left rows");
+        assert_eq!(vec![0, 2, 4, 5], right, "Wrong right rows");
+
+        let target = vec![true, false, true, false, true, false];
+        let node_target = TargetRows::new(&target, &rows[..n_left]);
+        assert_eq!(2, node_target.len(), "Wrong node length");
+        assert!(node_target.iter().all(|v| !v), "Wrong node target");
+    }
+    #[test]
+    fn test_weighted_target() {
+        let target = WeightedTarget::balanced(vec![true, false, false, false]);
+        assert_eq!(
+            &[2., 2. / 3., 2. / 3., 2. / 3.],
+            target.weights(),
+            "Wrong weights"
+        );
+
+        let mask = vec![Some(true), None, Some(false), Some(true)];
+        let (left, right) = target.split(mask.into_iter(), NullDirection::Left);
+        assert_eq!(
+            &vec![true, false, false],
+            left.target(),
+            "Wrong left target"
+        );
+        assert_eq!(
+            &[2., 2. / 3., 2. / 3.],
+            left.weights(),
+            "Wrong left weights"
+        );
+        assert_eq!(1, Splittable::len(&right), "Wrong right target");
+        assert_eq!(2. / 3., right.weight(0), "Wrong right weight");
+    }
+}
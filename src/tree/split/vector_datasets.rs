@@ -1,17 +1,21 @@
 use rayon::iter::IntoParallelRefIterator;
-use rayon::iter::ParallelBridge;
 use rayon::iter::ParallelIterator;
 
+use crate::tree::loss_fn::gradients::GradHess;
 use crate::tree::loss_fn::split_values::NullDirection;
 use crate::tree::loss_fn::split_values::SplitInfo;
 use crate::tree::loss_fn::Score;
 use crate::tree::split::Feature;
 use core::cmp::Ordering;
+use std::any::Any;
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::sync::Arc;
 
-use super::BestSplitNotFound;
+use super::histogram::{FeatureHistogram, Histograms};
 use super::DataSet;
 use super::DataSetRowsError;
+use super::Row;
 use super::Splittable;
 use super::Target;
 
@@ -30,7 +34,7 @@ where
         let mut right_values = Vec::with_capacity(self.len());
         let mut left_values = Vec::with_capacity(self.len());
 
-        for (value, should_go_left) in self.into_iter().zip(mask.map(|m| match m {
+        for (value, should_go_left) in self.iter().zip(mask.map(|m| match m {
             Some(b) => b,
             None => matches!(null_direction, NullDirection::Left),
         })) {
@@ -89,6 +93,48 @@ impl Target<bool> for std::vec::Vec<bool> {
     fn iter(&self) -> impl Iterator<Item = bool> {
         self.as_slice().iter().copied()
     }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn value(&self, row: usize) -> bool {
+        self[row]
+    }
+}
+
+impl Target<u32> for std::vec::Vec<u32> {
+    fn iter(&self) -> impl Iterator<Item = u32> {
+        self.as_slice().iter().copied()
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn value(&self, row: usize) -> u32 {
+        self[row]
+    }
+}
+
+impl Target<f64> for std::vec::Vec<f64> {
+    fn iter(&self) -> impl Iterator<Item = f64> {
+        self.as_slice().iter().copied()
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn value(&self, row: usize) -> f64 {
+        self[row]
+    }
+}
+
+impl Target<GradHess> for std::vec::Vec<GradHess> {
+    fn iter(&self) -> impl Iterator<Item = GradHess> {
+        self.as_slice().iter().copied()
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn value(&self, row: usize) -> GradHess {
+        self[row]
+    }
 }
 
 impl<F> Splittable for HashMap<String, std::vec::Vec<F>>
@@ -108,7 +154,7 @@ where
 
         let mask: Vec<_> = mask.collect();
 
-        for (column_name, values) in self.into_iter() {
+        for (column_name, values) in self.iter() {
             let (left_vals, right_values) = values.split(mask.iter().copied(), null_direction);
             left.insert(column_name.clone(), left_vals);
             right.insert(column_name.clone(), right_values);
@@ -119,37 +165,72 @@ where
     }
 }
 
+/// Histogram of the raw values of a feature: every distinct value of the node is a split
+/// candidate, unless the candidates of the parent are given.
+pub(super) fn value_histogram<F: Into<f64> + Copy, T, S: Score<T>>(
+    values: &[F],
+    rows: &[usize],
+    target: &impl Target<T>,
+    score_function: &S,
+    parent: Option<&FeatureHistogram<S::Stats>>,
+) -> FeatureHistogram<S::Stats> {
+    let thresholds: Arc<[f64]> = match parent {
+        Some(parent_histogram) => parent_histogram.thresholds.clone(),
+        None => {
+            let mut sorted: Vec<f64> = rows
+                .iter()
+                .map(|row| values[*row].into())
+                .filter(|v: &f64| !v.is_nan())
+                .collect();
+            sorted.sort_by(f64::total_cmp);
+            sorted.dedup();
+            sorted.into()
+        }
+    };
+    let bins = rows.iter().map(|row| {
+        let v: f64 = values[*row].into();
+        let bin = (!v.is_nan()).then(|| thresholds.partition_point(|t| *t <= v) - 1);
+        (bin, target.value(*row), target.weight(*row))
+    });
+    FeatureHistogram::build(thresholds.clone(), bins, score_function)
+}
+
+/// Routing of `rows`: values lower than `threshold` go left, NaN values are null.
+pub(super) fn threshold_mask<F: Into<f64> + Copy>(
+    values: &[F],
+    threshold: f64,
+    rows: &[usize],
+) -> Vec<Option<bool>> {
+    rows.iter()
+        .map(|row| {
+            let v: f64 = values[*row].into();
+            v.partial_cmp(&threshold)
+                .map(|ordering| ordering == Ordering::Less)
+        })
+        .collect()
+}
+
 impl<F> DataSet for HashMap<String, std::vec::Vec<F>>
 where
-    F: Into<f64> + PartialOrd + Copy + Send + Copy + Sync,
+    F: Into<f64> + PartialOrd + Copy + Send + Copy + Sync + 'static,
 {
-    fn find_best_split<T, S: Score<T>>(
+    fn histograms<T, S: Score<T>>(
         &self,
+        rows: &[usize],
         target: &impl Target<T>,
         score_function: &S,
-    ) -> Result<(SplitInfo, impl Iterator<Item = Option<bool>> + Clone), BestSplitNotFound> {
-        let min_sp = |s1: (SplitInfo, _), s2: (SplitInfo, _)| match s1.0.partial_cmp(&s2.0) {
-            Some(Ordering::Less) | Some(Ordering::Equal) => Ok(s1),
-            Some(Ordering::Greater) => Ok(s2),
-            None => Err(BestSplitNotFound::ScoreNotComparable((s1.0, s2.0))),
-        };
+        parent: Option<&Histograms<S::Stats>>,
+    ) -> Histograms<S::Stats> {
         self.par_iter()
-            .flat_map(|(name, values)| {
-                values.find_splits().par_bridge().map(move |split_val| {
-                    let mask = values.mask(split_val);
-                    let score = score_function.split_score(target, mask.clone())?;
-                    Ok((SplitInfo::new(name.clone(), split_val.into(), score), mask))
-                })
+            .map(|(name, values)| {
+                let parent = parent.and_then(|p| p.get(name));
+                let histogram = value_histogram(values, rows, target, score_function, parent);
+                (name.clone(), histogram)
             })
-            .reduce(
-                || Err(BestSplitNotFound::NoSplitRequired),
-                |acc, el| match (acc, el) {
-                    (Ok(acc), Ok(el)) => min_sp(acc, el),
-                    (Ok(acc), Err(_)) => Ok(acc),
-                    (Err(_), Ok(el)) => Ok(el),
-                    (Err(acc), Err(_)) => Err(BestSplitNotFound::from(acc)),
-                },
-            )
+            .collect()
+    }
+    fn split_mask(&self, split_info: &SplitInfo, rows: &[usize]) -> Vec<Option<bool>> {
+        threshold_mask(&self[&split_info.name], split_info.value, rows)
     }
     fn num_rows(&self) -> Result<usize, DataSetRowsError> {
         let max = self.values().map(|vec| vec.len()).max();
@@ -158,10 +239,18 @@ where
             None => Err(DataSetRowsError::EmptyDF),
         }
     }
+    fn column(&self, name: &str) -> Option<Cow<'_, [f64]>> {
+        let values = self.get(name)?;
+        // f64 columns are borrowed, only other types are converted
+        match (values as &dyn Any).downcast_ref::<Vec<f64>>() {
+            Some(values) => Some(Cow::Borrowed(values)),
+            None => Some(Cow::Owned(values.iter().map(|v| (*v).into()).collect())),
+        }
+    }
     fn rows(
         &self,
     ) -> Result<
-        impl Iterator<Item = Result<Vec<(&str, Option<impl Into<f64> + Copy>)>, DataSetRowsError>>,
+        impl Iterator<Item = Result<Row<'_, impl Into<f64> + Copy>, DataSetRowsError>>,
         DataSetRowsError,
     > {
         let indices = 0..self.num_rows()?;
@@ -184,12 +273,13 @@ mod test {
     use super::*;
     use crate::tree::loss_fn::Logit;
     use crate::tree::loss_fn::ScoringFunction;
+    use crate::tree::TreeConfig;
     #[test]
     fn test_logit_split() {
         let df = HashMap::from([("f1".to_owned(), vec![1., 2., 3.])]);
         let tar = vec![true, true, false];
         let score_fn = ScoringFunction::Logit(Logit::new(0.5));
-        if let Ok((split_info, _)) = df.find_best_split(&tar, &score_fn) {
+        if let Ok((split_info, _)) = df.find_best_split(&tar, &score_fn, &TreeConfig::default()) {
             println!(
                 "Split col: {}\nSplit val: {}",
                 split_info.name, split_info.value